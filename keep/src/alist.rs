@@ -1,77 +1,103 @@
-use crate::heaped::{Heap, Heaped};
-use std::sync::atomic::{AtomicPtr, Ordering};
+use crate::{
+    alloc::{Allocator, Global},
+    heaped::{Heap, Heaped},
+};
+use core::sync::atomic::{AtomicPtr, Ordering};
 
 
-pub struct Node<T>
+#[repr(C)]
+pub struct Node<T, A: Allocator + Copy = Global>
 {
-    head: Option<Heap<Node<T>>>,
+    head: Option<Heap<Node<T, A>, A>>,
     value: AtomicPtr<T>,
-    next: AtomicPtr<Node<T>>,
+    next: AtomicPtr<Node<T, A>>,
+    alloc: A,
 }
 
 
-impl<T> Node<T>
+impl<T, A: Allocator + Copy> Node<T, A>
 {
     /// Creates a new node without a next node
-    pub fn new(value: impl Heaped<T>, head: Option<Heap<Node<T>>>) -> Heap<Node<T>>
+    ///
+    /// Nodes only point to values owned by someone else, so `value` is taken as a `Heap`.
+    pub fn new(
+        value: Heap<T, A>,
+        head: Option<Heap<Node<T, A>, A>>,
+        alloc: A,
+    ) -> Heap<Node<T, A>, A>
     {
         unsafe {
             Self {
                 head,
-                value: AtomicPtr::new(value.heaped().as_ptr()),
-                next: AtomicPtr::new(std::ptr::null_mut()),
+                value: AtomicPtr::new(value.as_ptr()),
+                next: AtomicPtr::new(core::ptr::null_mut()),
+                alloc,
             }
-            .heaped()
+            .heaped_in(alloc)
         }
     }
 
     /// Frees the list
     pub unsafe fn free_list(&self)
     {
-        if let Some(next) = unsafe {
-            self.next
-                .swap(std::ptr::null_mut(), Ordering::AcqRel)
-                .as_ref()
-        }
+        let next = self.next.swap(core::ptr::null_mut(), Ordering::AcqRel);
+
+        if !next.is_null()
         {
             unsafe {
-                next.free_list();
-                next.heaped().free();
+                (*next).free_list();
+                Heap::from_ptr_in(next, self.alloc).free();
             }
         }
     }
 
-    /// Frees the list and its values
-    pub unsafe fn free_list_and_nodes(&self)
+    /// Appends empty nodes to this list until it has at least `len` nodes.
+    pub fn reserve(&self, len: usize)
     {
-        if let Some(next) = unsafe {
-            self.next
-                .swap(std::ptr::null_mut(), Ordering::AcqRel)
-                .as_ref()
-        }
+        let mut count = 1;
+        let mut tail = self;
+
+        loop
         {
-            unsafe {
-                next.free_list();
-                next.heaped().free();
+            if count >= len
+            {
+                return;
             }
-        }
 
-        let val = self.value.load(Ordering::Acquire);
-        if !val.is_null()
-        {
-            unsafe {
-                Heap::from_ptr(val).free();
+            if let Some(next) = unsafe { tail.next.load(Ordering::Acquire).as_ref() }
+            {
+                tail = next;
+                count += 1;
+                continue;
+            }
+
+            let new_node = Node::<T, A>::new(
+                unsafe { Heap::from_ptr_in(core::ptr::null_mut(), self.alloc) },
+                Some(self.head()),
+                self.alloc,
+            );
+
+            // If another node was appended in the meantime, free ours and continue with theirs.
+            if tail
+                .next
+                .compare_exchange(
+                    core::ptr::null_mut(),
+                    new_node.as_ptr(),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                unsafe { new_node.free() };
             }
         }
     }
 
-
     /// Finds a free node or appends a new one and then stores `new_val`.
     ///
     /// Returns a `Heap<Node<T>>` pointing to the node containing `new_val`.
-    pub fn insert(&self, new_val: impl Heaped<T>) -> Heap<Node<T>>
+    pub fn insert(&self, new_val: Heap<T, A>) -> Heap<Node<T, A>, A>
     {
-        let new_val = unsafe { new_val.heaped() };
         let current_val = self.value.load(Ordering::Acquire);
 
         // If the current value is null, try to use this node to store new_val
@@ -87,7 +113,7 @@ impl<T> Node<T>
                 .is_ok()
         {
             // NOTE: This assumes that self is on the heap!!!
-            return unsafe { Heap::from_ptr(self as *const _ as _) };
+            return unsafe { Heap::from_ptr_in(self as *const _ as _, self.alloc) };
         }
 
         // If this node is not clear, try to store new_val in the next node
@@ -97,10 +123,10 @@ impl<T> Node<T>
         }
 
         // If there is no next node, create a new node and append it in the list
-        let new_node = Node::<T>::new(new_val, Some(self.head()));
+        let new_node = Node::<T, A>::new(new_val, Some(self.head()), self.alloc);
 
         match self.next.compare_exchange(
-            std::ptr::null_mut(),
+            core::ptr::null_mut(),
             new_node.as_ptr(),
             Ordering::Release,
             Ordering::Acquire,
@@ -119,7 +145,7 @@ impl<T> Node<T>
         self.value
             .compare_exchange(
                 current,
-                std::ptr::null_mut(),
+                core::ptr::null_mut(),
                 Ordering::Release,
                 Ordering::Relaxed,
             )
@@ -129,7 +155,7 @@ impl<T> Node<T>
     /// Clears the value of a node
     pub fn clear_unchecked(&self)
     {
-        self.value.store(std::ptr::null_mut(), Ordering::Release);
+        self.value.store(core::ptr::null_mut(), Ordering::Release);
     }
 
     /// Returns `true` if this list contained a pointer `ptr`.
@@ -173,7 +199,7 @@ impl<T> Node<T>
     pub fn contains_or_empty(&self, ptr: *mut T) -> Option<bool>
     {
         let mut is_empty = true;
-        let mut current = self as *const _ as *mut Node<T>;
+        let mut current = self as *const _ as *mut Node<T, A>;
 
         while let Some(curr) = unsafe { current.as_ref() }
         {
@@ -199,10 +225,10 @@ impl<T> Node<T>
     }
 
     /// Returns the head of this list
-    pub fn head(&self) -> Heap<Node<T>>
+    pub fn head(&self) -> Heap<Node<T, A>, A>
     {
         //NOTE: this assumes that self is on the heap
         self.head
-            .unwrap_or(unsafe { Heap::from_ptr(self as *const _ as _) })
+            .unwrap_or(unsafe { Heap::from_ptr_in(self as *const _ as _, self.alloc) })
     }
 }
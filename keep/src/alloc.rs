@@ -0,0 +1,139 @@
+//! Allocator plumbing.
+//!
+//! With the `allocator_api` feature enabled, the unstable `core::alloc::Allocator` trait is used
+//! and any `Copy` allocator can back a `Keep`. Otherwise a minimal stand-in is provided which
+//! only supports the `Global` allocator.
+
+
+use alloc_crate::boxed::Box;
+
+#[cfg(feature = "allocator_api")]
+pub use alloc_crate::alloc::{Allocator, Global};
+
+#[cfg(not(feature = "allocator_api"))]
+pub use stub::{Allocator, Global};
+
+
+/// Moves `val` into memory allocated from `alloc` and returns a raw pointer to it.
+#[cfg(feature = "allocator_api")]
+#[inline]
+pub(crate) fn box_in<T, A: Allocator>(val: T, alloc: A) -> *mut T
+{
+    track_alloc::<T>();
+    Box::into_raw_with_allocator(Box::new_in(val, alloc)).0
+}
+
+
+/// Drops and frees a `T` previously allocated through `box_in`.
+///
+/// # Safety
+/// `ptr` must have been allocated from `alloc` and must not be used again after this call.
+#[cfg(feature = "allocator_api")]
+#[inline]
+pub(crate) unsafe fn free_in<T, A: Allocator>(ptr: *mut T, alloc: A)
+{
+    track_free::<T>();
+    drop(unsafe { Box::from_raw_in(ptr, alloc) })
+}
+
+
+/// Moves a `T` previously allocated through `box_in` out of its allocation and frees the memory.
+///
+/// # Safety
+/// `ptr` must have been allocated from `alloc` and must not be used again after this call.
+#[cfg(feature = "allocator_api")]
+#[inline]
+pub(crate) unsafe fn take_in<T, A: Allocator>(ptr: *mut T, alloc: A) -> T
+{
+    track_free::<T>();
+    *unsafe { Box::from_raw_in(ptr, alloc) }
+}
+
+
+/// Frees the memory of a `T` previously allocated through `box_in` without dropping it.
+///
+/// # Safety
+/// `ptr` must have been allocated from `alloc` and must not be used again after this call.
+#[cfg(feature = "allocator_api")]
+#[inline]
+pub(crate) unsafe fn dealloc_in<T, A: Allocator>(ptr: *mut T, alloc: A)
+{
+    track_free::<T>();
+    drop(unsafe { Box::from_raw_in(ptr as *mut core::mem::MaybeUninit<T>, alloc) })
+}
+
+
+#[cfg(not(feature = "allocator_api"))]
+#[inline]
+pub(crate) fn box_in<T, A: Allocator>(val: T, _alloc: A) -> *mut T
+{
+    track_alloc::<T>();
+    Box::into_raw(Box::new(val))
+}
+
+
+#[cfg(not(feature = "allocator_api"))]
+#[inline]
+pub(crate) unsafe fn free_in<T, A: Allocator>(ptr: *mut T, _alloc: A)
+{
+    track_free::<T>();
+    drop(unsafe { Box::from_raw(ptr) })
+}
+
+
+#[cfg(not(feature = "allocator_api"))]
+#[inline]
+pub(crate) unsafe fn take_in<T, A: Allocator>(ptr: *mut T, _alloc: A) -> T
+{
+    track_free::<T>();
+    *unsafe { Box::from_raw(ptr) }
+}
+
+
+#[cfg(not(feature = "allocator_api"))]
+#[inline]
+pub(crate) unsafe fn dealloc_in<T, A: Allocator>(ptr: *mut T, _alloc: A)
+{
+    track_free::<T>();
+    drop(unsafe { Box::from_raw(ptr as *mut core::mem::MaybeUninit<T>) })
+}
+
+
+/// Counts an allocation of a `T` towards `metrics::allocated_bytes`.
+#[inline]
+pub(crate) fn track_alloc<T>()
+{
+    #[cfg(feature = "metrics")]
+    crate::metrics::allocated(core::mem::size_of::<T>());
+}
+
+
+/// Counts freeing a `T` towards `metrics::allocated_bytes`.
+#[inline]
+pub(crate) fn track_free<T>()
+{
+    #[cfg(feature = "metrics")]
+    crate::metrics::freed(core::mem::size_of::<T>());
+}
+
+
+#[cfg(not(feature = "allocator_api"))]
+mod stub
+{
+    mod sealed
+    {
+        pub trait Sealed {}
+    }
+
+    /// Stand-in for `core::alloc::Allocator` used when the `allocator_api` feature is disabled.
+    ///
+    /// This trait is sealed and only implemented by `Global`.
+    pub trait Allocator: sealed::Sealed {}
+
+    /// The global memory allocator.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct Global;
+
+    impl sealed::Sealed for Global {}
+    impl Allocator for Global {}
+}
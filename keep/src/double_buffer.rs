@@ -0,0 +1,61 @@
+use crate::{guard::Guard, heaped::Heaped, keep::Keep};
+
+
+/// Two buffers of which readers use the front one, while the back one is being prepared.
+///
+/// Both buffers are stored in a single `Keep`, so `flip` swaps them atomically:
+/// readers observe either the old or the new front buffer, never a state in between.
+pub struct DoubleBuffer<T>
+{
+    // (front, back)
+    buffers: Keep<(Guard<T>, Guard<T>)>,
+}
+
+
+impl<T> DoubleBuffer<T>
+{
+    pub fn new(front: impl Heaped<T>, back: impl Heaped<T>) -> Self
+    {
+        Self {
+            buffers: Keep::new((Keep::new(front).read(), Keep::new(back).read())),
+        }
+    }
+
+    /// Returns a guard to the current front buffer.
+    pub fn front(&self) -> Guard<T>
+    {
+        self.buffers.read().0.clone()
+    }
+
+    /// Returns a guard to the current back buffer.
+    pub fn back(&self) -> Guard<T>
+    {
+        self.buffers.read().1.clone()
+    }
+
+    /// Replaces the back buffer with `val`, the front buffer is left untouched.
+    pub fn write_back(&self, val: impl Heaped<T>)
+    {
+        let back = Keep::new(val).read();
+        self.buffers
+            .update_and_fetch(|(front, _)| (front.clone(), back.clone()));
+    }
+
+    /// Atomically makes the back buffer the front buffer and vice versa.
+    pub fn flip(&self)
+    {
+        self.buffers
+            .update_and_fetch(|(front, back)| (back.clone(), front.clone()));
+    }
+}
+
+
+impl<T> Clone for DoubleBuffer<T>
+{
+    fn clone(&self) -> Self
+    {
+        Self {
+            buffers: self.buffers.clone(),
+        }
+    }
+}
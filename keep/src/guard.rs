@@ -1,38 +1,106 @@
 use crate::{
     alist::Node,
+    alloc::{Allocator, Global},
     heaped::Heap,
+    keep::Keep,
     tracked_atomic::{Mutation, TrackedAtomic},
 };
-use std::ops::Deref;
+use alloc_crate::boxed::Box;
+use core::{any::TypeId, mem::ManuallyDrop, ops::Deref};
 
 
-pub struct Guard<T>
+pub struct Guard<T, A: Allocator + Copy = Global>
 {
-    pub(crate) ptr: Heap<Mutation<T>>,
-    pub(crate) node: Heap<Node<Mutation<T>>>,
-    pub(crate) tracked_atomic: Heap<TrackedAtomic<T>>,
+    pub(crate) ptr: Heap<Mutation<T, A>, A>,
+    pub(crate) node: Heap<Node<Mutation<T, A>, A>, A>,
+    pub(crate) tracked_atomic: Heap<TrackedAtomic<T, A>, A>,
 }
 
 
-impl<T: std::fmt::Debug> std::fmt::Debug for Guard<T>
+impl<T, A: Allocator + Copy> Guard<T, A>
 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    /// Consumes this guard and returns a `Keep` to the cell it was read from.
+    ///
+    /// Writes through the returned `Keep` are visible to all other handles of the cell.
+    /// Note that the `Keep` refers to the current value of the cell, which might have
+    /// been replaced since this guard was read.
+    pub fn into_keep(self) -> Keep<T, A>
+    {
+        // Register the new accessor before this guard stops protecting the cell.
+        Keep::from_tracked_atomic(self.tracked_atomic)
+    }
+
+    /// Returns `true` if both guards protect the same value, rather than merely equal ones.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool
+    {
+        this.ptr.as_ptr() == other.ptr.as_ptr()
+    }
+
+    /// Returns `true` if this guard is an accessor of an immutable value instead of a domain entry.
+    #[inline]
+    fn is_immutable(&self) -> bool
+    {
+        self.node.as_ptr().is_null()
+    }
+}
+
+
+impl<D: ?Sized, A: Allocator + Copy> Guard<Box<D>, A>
+{
+    /// Converts a guard to a boxed trait object into a guard to the boxed `U`.
+    ///
+    /// Only values of a `Keep` created by `Keep::new_tagged` can be downcast,
+    /// `self` is returned if the value is untagged or not a `U`.
+    pub fn downcast<U: 'static>(self) -> Result<Guard<Box<U>, A>, Self>
+    {
+        if self.tracked_atomic.tag() != Some(TypeId::of::<U>())
+        {
+            return Err(self);
+        }
+
+        let Some(view) = self.tracked_atomic.tagged_view()
+        else
+        {
+            return Err(self);
+        };
+
+        let guard = ManuallyDrop::new(self);
+        let alloc = guard.tracked_atomic.allocator();
+
+        // SAFETY: The view holds the thin pointer to the boxed `U` in an `AtomicPtr`, which has the layout of a `Box<U>`.
+        // `Mutation`, `Node` and `TrackedAtomic` are `repr(C)` and only refer to their value through pointers,
+        // so their layout does not depend on it. Tagged values are immutable and are only freed by
+        // `TrackedAtomic::dispose`, which frees them and the view as the types they were created as.
+        unsafe {
+            Ok(Guard {
+                ptr: Heap::from_ptr_in(view.as_ptr().cast(), alloc),
+                node: Heap::from_ptr_in(guard.node.as_ptr().cast(), alloc),
+                tracked_atomic: Heap::from_ptr_in(guard.tracked_atomic.as_ptr().cast(), alloc),
+            })
+        }
+    }
+}
+
+
+impl<T: core::fmt::Debug, A: Allocator + Copy> core::fmt::Debug for Guard<T, A>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
     {
         self.ptr.borrow().fmt(f)
     }
 }
 
 
-impl<T: std::fmt::Display> std::fmt::Display for Guard<T>
+impl<T: core::fmt::Display, A: Allocator + Copy> core::fmt::Display for Guard<T, A>
 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
     {
         self.ptr.borrow().fmt(f)
     }
 }
 
 
-impl<T: PartialEq> PartialEq for Guard<T>
+impl<T: PartialEq, A: Allocator + Copy> PartialEq for Guard<T, A>
 {
     fn eq(&self, other: &Self) -> bool
     {
@@ -41,7 +109,7 @@ impl<T: PartialEq> PartialEq for Guard<T>
 }
 
 
-impl<T> Deref for Guard<T>
+impl<T, A: Allocator + Copy> Deref for Guard<T, A>
 {
     type Target = T;
 
@@ -52,7 +120,7 @@ impl<T> Deref for Guard<T>
 }
 
 
-impl<T> AsRef<T> for Guard<T>
+impl<T, A: Allocator + Copy> AsRef<T> for Guard<T, A>
 {
     fn as_ref(&self) -> &T
     {
@@ -61,10 +129,21 @@ impl<T> AsRef<T> for Guard<T>
 }
 
 
-impl<T> Clone for Guard<T>
+impl<T, A: Allocator + Copy> Clone for Guard<T, A>
 {
     fn clone(&self) -> Self
     {
+        if self.is_immutable()
+        {
+            self.tracked_atomic.register_accessor();
+
+            return Self {
+                ptr: self.ptr,
+                node: self.node,
+                tracked_atomic: self.tracked_atomic,
+            };
+        }
+
         Self {
             ptr: self.ptr,
             node: self.node.head().insert(self.ptr),
@@ -74,11 +153,32 @@ impl<T> Clone for Guard<T>
 }
 
 
-impl<T> Drop for Guard<T>
+impl<T, A: Allocator + Copy> Drop for Guard<T, A>
 {
     fn drop(&mut self)
     {
-        self.node.clear(self.ptr.as_ptr());
-        self.tracked_atomic.try_drop(self.ptr);
+        // Guards and `Keep`s released concurrently agree on who disposes the tracked atomic, see `release`.
+        if self.is_immutable()
+        {
+            unsafe {
+                TrackedAtomic::release(self.tracked_atomic, |tracked_atomic| {
+                    if tracked_atomic.unregister_accessor()
+                    {
+                        tracked_atomic.dispose_if_dead();
+                    }
+                })
+            };
+
+            return;
+        }
+
+        // Once cleared, the value is not guarded by this guard anymore.
+        unsafe {
+            TrackedAtomic::release(self.tracked_atomic, |tracked_atomic| {
+                self.node.clear(self.ptr.as_ptr());
+                tracked_atomic.try_drop(self.ptr);
+                tracked_atomic.dispose_if_dead();
+            })
+        };
     }
 }
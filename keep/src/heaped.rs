@@ -1,7 +1,12 @@
+use crate::alloc::{self, Allocator, Global};
+use alloc_crate::boxed::Box;
+
+
 /// Holds a pointer to a value on the heap.
 ///
 /// A `Heap<T>` does not free `T` on drop.
-pub struct Heap<T>(*mut T);
+#[repr(C)]
+pub struct Heap<T, A: Allocator + Copy = Global>(*mut T, A);
 
 
 impl<T> Heap<T>
@@ -14,7 +19,22 @@ impl<T> Heap<T>
     #[inline]
     pub unsafe fn from_ptr(ptr: *mut T) -> Self
     {
-        Self(ptr)
+        unsafe { Self::from_ptr_in(ptr, Global) }
+    }
+}
+
+
+impl<T, A: Allocator + Copy> Heap<T, A>
+{
+    /// Creates a new `Heap<T, A>` from a pointer to a `T` allocated from `alloc`.
+    ///
+    /// # Safety
+    /// The caller needs to ensure that `ptr` is indeed valid a pointer to a
+    /// `T` allocated from `alloc`. This `T` must not be freed/dropped by anything other than `Heap::free`.
+    #[inline]
+    pub unsafe fn from_ptr_in(ptr: *mut T, alloc: A) -> Self
+    {
+        Self(ptr, alloc)
     }
 
     /// Drops and frees the contained `T`
@@ -25,7 +45,28 @@ impl<T> Heap<T>
     #[inline]
     pub unsafe fn free(self)
     {
-        drop(unsafe { Box::from_raw(self.0) })
+        unsafe { alloc::free_in(self.0, self.1) }
+    }
+
+    /// Moves the contained `T` out and frees its memory without dropping it.
+    ///
+    /// # Safety
+    /// Same as `Heap::free`.
+    #[inline]
+    pub unsafe fn take(self) -> T
+    {
+        unsafe { alloc::take_in(self.0, self.1) }
+    }
+
+    /// Frees the memory of the contained `T` without dropping it.
+    ///
+    /// # Safety
+    /// Same as `Heap::free`, additionally the contained `T` must have already been dropped
+    /// or moved out, otherwise it is leaked.
+    #[inline]
+    pub unsafe fn dealloc(self)
+    {
+        unsafe { alloc::dealloc_in(self.0, self.1) }
     }
 
     #[inline]
@@ -33,11 +74,18 @@ impl<T> Heap<T>
     {
         self.0
     }
+
+    /// Returns the allocator the contained `T` was allocated from.
+    #[inline]
+    pub fn allocator(&self) -> A
+    {
+        self.1
+    }
 }
 
 
-impl<T> Copy for Heap<T> {}
-impl<T> Clone for Heap<T>
+impl<T, A: Allocator + Copy> Copy for Heap<T, A> {}
+impl<T, A: Allocator + Copy> Clone for Heap<T, A>
 {
     fn clone(&self) -> Self
     {
@@ -46,7 +94,7 @@ impl<T> Clone for Heap<T>
 }
 
 
-impl<T> AsRef<T> for Heap<T>
+impl<T, A: Allocator + Copy> AsRef<T> for Heap<T, A>
 {
     fn as_ref(&self) -> &T
     {
@@ -55,7 +103,7 @@ impl<T> AsRef<T> for Heap<T>
 }
 
 
-impl<T> std::ops::Deref for Heap<T>
+impl<T, A: Allocator + Copy> core::ops::Deref for Heap<T, A>
 {
     type Target = T;
 
@@ -67,45 +115,75 @@ impl<T> std::ops::Deref for Heap<T>
 }
 
 
-unsafe impl<T> Send for Heap<T> where T: Sync {}
-unsafe impl<T> Sync for Heap<T> where T: Sync {}
+unsafe impl<T, A: Allocator + Copy + Send> Send for Heap<T, A> where T: Sync {}
+unsafe impl<T, A: Allocator + Copy + Sync> Sync for Heap<T, A> where T: Sync {}
 
 
 /// Provides a method to move the implementing object of `T` on the heap and returns a `Heap<T>` to it.
-pub trait Heaped<T>
+pub trait Heaped<T, A: Allocator + Copy = Global>
 {
-    /// Moves `self` into the heap and returns a `Heap<T>` pointing to a `T`.
+    /// Moves `self` into memory allocated from `alloc` and returns a `Heap<T, A>` pointing to a `T`.
+    ///
+    /// Values that already live on the heap are expected to have been allocated from `alloc`.
     ///
     /// # Safety
-    /// Calling `Heaped::heaped` on a value will move it onto the heap without ever
+    /// Calling `Heaped::heaped_in` on a value will move it onto the heap without ever
     /// dropping it again, leaking `T` if `Heap::free` is never manually called.
     /// Therefore the caller needs to ensure that `T` is eventually correctly dropped.
-    unsafe fn heaped(self) -> Heap<T>;
+    unsafe fn heaped_in(self, alloc: A) -> Heap<T, A>;
+
+    /// Moves `self` into the heap and returns a `Heap<T>` pointing to a `T`.
+    ///
+    /// # Safety
+    /// See `Heaped::heaped_in`.
+    unsafe fn heaped(self) -> Heap<T, A>
+    where
+        Self: Sized,
+        A: Default,
+    {
+        unsafe { self.heaped_in(A::default()) }
+    }
 }
 
 
-impl<T> Heaped<T> for T
+impl<T, A: Allocator + Copy> Heaped<T, A> for T
 {
-    unsafe fn heaped(self) -> Heap<T>
+    unsafe fn heaped_in(self, alloc: A) -> Heap<T, A>
     {
-        Heap(Box::into_raw(Box::new(self)))
+        Heap(alloc::box_in(self, alloc), alloc)
     }
 }
 
 
+#[cfg(not(feature = "allocator_api"))]
 impl<T> Heaped<T> for Box<T>
 {
-    unsafe fn heaped(self) -> Heap<T>
+    unsafe fn heaped_in(self, alloc: Global) -> Heap<T>
+    {
+        alloc::track_alloc::<T>();
+        Heap(Box::into_raw(self), alloc)
+    }
+}
+
+
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator + Copy> Heaped<T, A> for Box<T, A>
+{
+    unsafe fn heaped_in(self, _alloc: A) -> Heap<T, A>
     {
-        Heap(Box::into_raw(self))
+        alloc::track_alloc::<T>();
+        let (ptr, alloc) = Box::into_raw_with_allocator(self);
+        Heap(ptr, alloc)
     }
 }
 
 
-impl<T> Heaped<T> for Heap<T>
+impl<T, A: Allocator + Copy> Heaped<T, A> for Heap<T, A>
 {
-    unsafe fn heaped(self) -> Heap<T>
+    unsafe fn heaped_in(self, _alloc: A) -> Heap<T, A>
     {
+        // The `T` was allocated outside of keep, but keep frees it from now on.
+        alloc::track_alloc::<T>();
         self
     }
 }
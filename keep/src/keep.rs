@@ -1,18 +1,34 @@
-use std::sync::atomic::{AtomicPtr, Ordering};
+use alloc_crate::boxed::Box;
+use core::{
+    any::TypeId,
+    mem::ManuallyDrop,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
 
 use crate::{
+    alloc::{Allocator, Global},
     guard::Guard,
     heaped::{Heap, Heaped},
     tracked_atomic::TrackedAtomic,
 };
 
 
-pub struct KeepMarker<T>(*mut TrackedAtomic<T>);
+pub struct KeepMarker<T, A: Allocator + Copy = Global>(*mut TrackedAtomic<T, A>);
 
 
-pub struct Keep<T>
+pub struct Keep<T, A: Allocator + Copy = Global>
 {
-    tracked_atomic: AtomicPtr<AtomicPtr<TrackedAtomic<T>>>,
+    cell: AtomicPtr<KeepCell<T, A>>,
+}
+
+
+/// The cell shared by a `Keep` and its clones, which `swap_with` swaps the tracked atomic of.
+struct KeepCell<T, A: Allocator + Copy>
+{
+    tracked_atomic: AtomicPtr<TrackedAtomic<T, A>>,
+
+    // The number of `Keep`s sharing this cell, the last one frees it.
+    handles: AtomicUsize,
 }
 
 
@@ -20,47 +36,187 @@ impl<T> Keep<T>
 {
     pub fn new(val: impl Heaped<T>) -> Self
     {
-        let me = Self {
-            tracked_atomic: AtomicPtr::new(unsafe {
-                AtomicPtr::new(TrackedAtomic::new(val).as_ptr())
-                    .heaped()
-                    .as_ptr()
-            }),
+        Self::new_with(val, Global)
+    }
+
+    /// Like `new`, but fails if the allocation limit was reached, see `metrics::set_allocation_limit`.
+    #[cfg(feature = "metrics")]
+    pub fn try_new(val: impl Heaped<T>) -> Result<Self, crate::metrics::LimitExceeded>
+    {
+        if crate::metrics::limit_exceeded()
+        {
+            return Err(crate::metrics::LimitExceeded);
+        }
+
+        Ok(Self::new(val))
+    }
+
+    /// Creates a new `Keep` whose value is never replaced.
+    ///
+    /// Reading such a `Keep` only registers the guard as an accessor, which is much cheaper than
+    /// a regular read and never allocates. Writing to it (or any of its clones) panics.
+    pub fn new_immutable(val: impl Heaped<T>) -> Self
+    {
+        Self::from_tracked_atomic(TrackedAtomic::new_immutable(val, Global))
+    }
+
+    /// Creates a new `Keep` which is prepared for `expected_readers` guards being alive at once.
+    ///
+    /// Reading normally allocates bookkeeping the first time more guards are alive than ever
+    /// before, which is done up front here to keep those allocations off the hot path.
+    pub fn with_reader_hint(val: impl Heaped<T>, expected_readers: usize) -> Self
+    {
+        let keep = Self::new(val);
+        keep.tracked_atomic().reserve_readers(expected_readers);
+        keep
+    }
+}
+
+
+impl<D: ?Sized> Keep<Box<D>>
+{
+    /// Creates a new immutable `Keep` of a boxed trait object, which remembers the concrete type `tag`
+    /// of the boxed value, so guards to it can be downcast with `Guard::downcast`.
+    ///
+    /// Like for `new_immutable`, writing to the returned `Keep` panics, since a written value
+    /// would not match the tag.
+    ///
+    /// # Safety
+    /// `tag` must be the `TypeId` of the concrete type `val` was unsized from.
+    pub unsafe fn new_tagged(val: Box<D>, tag: TypeId) -> Self
+    {
+        Self::from_tracked_atomic(unsafe {
+            TrackedAtomic::new_tagged(val, tag, |val| &**val as *const D as *mut (), Global)
+        })
+    }
+}
+
+
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator + Copy> Keep<T, A>
+{
+    /// Creates a new `Keep` whose control block and values are allocated from `alloc`.
+    ///
+    /// Values passed to `write`, `swap` or `exchange` are moved into `alloc` as well.
+    /// Values that already live on the heap (`Box<T, A>`, `Heap<T, A>`) must have been allocated from `alloc`.
+    pub fn new_in(val: impl Heaped<T, A>, alloc: A) -> Self
+    {
+        Self::new_with(val, alloc)
+    }
+}
+
+
+impl<T, A: Allocator + Copy> Keep<T, A>
+{
+    fn new_with(val: impl Heaped<T, A>, alloc: A) -> Self
+    {
+        Self::from_tracked_atomic(TrackedAtomic::new(val, alloc))
+    }
+
+    /// Creates a new `Keep` handle to the value cell of `tracked_atomic`.
+    pub(crate) fn from_tracked_atomic(tracked_atomic: Heap<TrackedAtomic<T, A>, A>) -> Self
+    {
+        tracked_atomic.register_accessor();
+
+        let cell = KeepCell {
+            tracked_atomic: AtomicPtr::new(tracked_atomic.as_ptr()),
+            handles: AtomicUsize::new(1),
         };
 
-        unsafe {
-            &*me.tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .load(Ordering::Acquire)
+        Self {
+            cell: AtomicPtr::new(unsafe { cell.heaped_in(tracked_atomic.allocator()).as_ptr() }),
         }
-        .register_accessor();
-        me
     }
 
-    pub fn read(&self) -> Guard<T>
+    pub fn read(&self) -> Guard<T, A>
     {
-        unsafe {
-            &*self
-                .tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .load(Ordering::Acquire)
+        self.tracked_atomic().load()
+    }
+
+    /// Returns a copy of the current value.
+    ///
+    /// Unlike `*keep.read()`, this doesn't register a guard, which makes it considerably cheaper for
+    /// small `Copy` values like counters. Replaced values are not freed while a copy is in progress.
+    #[inline]
+    pub fn get(&self) -> T
+    where
+        T: Copy,
+    {
+        self.tracked_atomic().get()
+    }
+
+    /// Returns a reference to the current value without registering a guard for it.
+    ///
+    /// This skips the bookkeeping of `read`, for hot loops in which the value is known to stay put.
+    ///
+    /// # Safety
+    /// While the returned reference is alive, no value may be stored in `self` or any of its
+    /// clones, neither by `write`, `swap`, `exchange`, `update_and_fetch`, `swap_with` or any other
+    /// method replacing the value, on this or any other thread. Once the value was replaced,
+    /// it can be freed at any time and the reference must not be used anymore.
+    #[inline]
+    pub unsafe fn borrow_unchecked(&self) -> &T
+    {
+        unsafe { self.tracked_atomic().current_unchecked() }
+    }
+
+    /// Reads the current value and returns a guard to it together with a raw pointer to the value.
+    ///
+    /// The pointer stays valid for as long as the returned guard is alive and must
+    /// not be dereferenced after the guard was dropped.
+    pub fn read_pinned(&self) -> (Guard<T, A>, *const T)
+    {
+        let guard = self.read();
+        let ptr = guard.ptr.inner() as *const T;
+
+        (guard, ptr)
+    }
+
+    /// Returns the version of `self`, which advances every time a new value is stored.
+    pub fn version(&self) -> u64
+    {
+        self.tracked_atomic().version()
+    }
+
+    /// Reads the current value and its version, unless the version is still `last_seen`.
+    ///
+    /// The returned version is the version of the returned value, so polling with it never misses an update.
+    pub fn read_if_changed(&self, last_seen: u64) -> Option<(Guard<T, A>, u64)>
+    {
+        if self.version() == last_seen
+        {
+            return None;
         }
-        .load()
+
+        Some(self.read_versioned())
     }
 
-    pub fn read_marked(&self) -> (Guard<T>, KeepMarker<T>)
+    /// Reads the current value together with its version.
+    pub fn read_versioned(&self) -> (Guard<T, A>, u64)
     {
-        let tracked_atomic = unsafe {
-            self.tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .load(Ordering::Acquire)
-        };
+        let guard = self.read();
+        let version = guard.ptr.version();
+
+        (guard, version)
+    }
+
+    /// Replaces the value of `self` with `new`, but only if its version is still `expected_version`.
+    ///
+    /// Returns the old value on success, or the current version if another value was stored in the meantime.
+    /// A value derived from `read_versioned` can be written back like this without losing concurrent updates.
+    pub fn replace_if_version(
+        &self,
+        expected_version: u64,
+        new: impl Heaped<T, A>,
+    ) -> Result<Guard<T, A>, u64>
+    {
+        self.tracked_atomic()
+            .replace_if_version(expected_version, new)
+    }
+
+    pub fn read_marked(&self) -> (Guard<T, A>, KeepMarker<T, A>)
+    {
+        let tracked_atomic = self.cell().tracked_atomic.load(Ordering::Acquire);
 
         let marker = KeepMarker(tracked_atomic);
         let guard = unsafe { &*tracked_atomic }.load();
@@ -68,102 +224,127 @@ impl<T> Keep<T>
         (guard, marker)
     }
 
-    pub fn write(&self, val: impl Heaped<T>)
+    pub fn write(&self, val: impl Heaped<T, A>)
     {
-        unsafe {
-            &*self
-                .tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .load(Ordering::Acquire)
-        }
-        .store(val)
+        self.tracked_atomic().store(val)
     }
 
-    pub fn swap(&self, new_value: impl Heaped<T>) -> Guard<T>
+    /// Stores `val` unless it equals the current value, returns `true` if it was stored.
+    ///
+    /// Skipping equal values saves the allocation of a new value cell, which makes this the better
+    /// fit for change detection loops. The comparison and the write are not one atomic step,
+    /// so like with `write`, a value stored concurrently in between is overwritten.
+    pub fn write_if_changed(&self, val: T) -> bool
+    where
+        T: PartialEq,
     {
-        unsafe {
-            &*self
-                .tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .load(Ordering::Acquire)
+        if *self.read() == val
+        {
+            return false;
         }
-        .swap(new_value)
+
+        self.write(val);
+        true
+    }
+
+    /// Stores `val` and hands back a guard to the previous value.
+    ///
+    /// This is the same as `swap`, use `write` if the previous value is not needed.
+    #[inline]
+    pub fn write_returning(&self, val: impl Heaped<T, A>) -> Guard<T, A>
+    {
+        self.swap(val)
+    }
+
+    pub fn swap(&self, new_value: impl Heaped<T, A>) -> Guard<T, A>
+    {
+        self.tracked_atomic().swap(new_value)
     }
 
     pub fn exchange(
         &self,
-        current: &Guard<T>,
-        new_value: impl Heaped<T>,
-    ) -> Result<Guard<T>, Guard<T>>
+        current: &Guard<T, A>,
+        new_value: impl Heaped<T, A>,
+    ) -> Result<Guard<T, A>, Guard<T, A>>
     {
-        unsafe {
-            &*self
-                .tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .load(Ordering::Acquire)
-        }
-        .exchange(current, new_value)
+        self.tracked_atomic().exchange(current, new_value)
     }
 
-    pub fn swap_with(&self, other: &Keep<T>)
+    /// Like `exchange`, but returns a guard to the new value on success.
+    pub fn exchange_fetch(
+        &self,
+        current: &Guard<T, A>,
+        new_value: impl Heaped<T, A>,
+    ) -> Result<Guard<T, A>, Guard<T, A>>
     {
-        let a = unsafe {
-            self.tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .load(Ordering::Acquire)
-        };
-        let b = unsafe {
-            other
-                .tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .swap(a, Ordering::SeqCst)
-        };
-        unsafe {
-            self.tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .store(b, Ordering::Release)
-        };
+        self.tracked_atomic().exchange_fetch(current, new_value)
     }
 
-    pub fn swap_with_marked(&self, marker: KeepMarker<T>, other: &Keep<T>) -> bool
+    /// Atomically replaces the current value with `f(current)` and returns a guard to the new value.
+    ///
+    /// `f` may be called multiple times if other threads modify the value concurrently.
+    pub fn update_and_fetch(&self, mut f: impl FnMut(&T) -> T) -> Guard<T, A>
     {
-        let other_ta = unsafe {
-            other
-                .tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .load(Ordering::Acquire)
-        };
+        let tracked_atomic = self.tracked_atomic();
+        let mut current = tracked_atomic.load();
 
-        if let Ok(self_ta) = unsafe {
-            self.tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .compare_exchange(marker.0, other_ta, Ordering::AcqRel, Ordering::Relaxed)
+        loop
+        {
+            match tracked_atomic.exchange_fetch(&current, f(&current))
+            {
+                Ok(new) => return new,
+                Err(actual) => current = actual,
+            }
         }
+    }
+
+    /// Copy-on-write update: clones the current value, applies `f` to the clone and publishes it,
+    /// returns a guard to the new value.
+    ///
+    /// Readers keep observing the old value until the clone is published.
+    /// Like `update_and_fetch`, this retries with a fresh clone if another thread modified the value concurrently.
+    pub fn cow_update(&self, mut f: impl FnMut(&mut T)) -> Guard<T, A>
+    where
+        T: Clone,
+    {
+        self.update_and_fetch(|current| {
+            let mut copy = current.clone();
+            f(&mut copy);
+            copy
+        })
+    }
+
+    /// Swaps the value cells of `self` and `other`.
+    ///
+    /// Concurrent readers of either `Keep` observe either the old or the new cell, never a torn one.
+    /// Concurrent `swap_with` calls involving the same `Keep`s are not atomic with respect to each other.
+    pub fn swap_with(&self, other: &Keep<T, A>)
+    {
+        let self_cell = &self.cell().tracked_atomic;
+        let other_cell = &other.cell().tracked_atomic;
+
+        // Both cells are published with release semantics and every exchange acquires the
+        // previous cell, so a reader loading either side synchronizes with the swap that stored it.
+        let a = self_cell.load(Ordering::Acquire);
+        let b = other_cell.swap(a, Ordering::AcqRel);
+        self_cell.swap(b, Ordering::AcqRel);
+    }
+
+    pub fn swap_with_marked(&self, marker: KeepMarker<T, A>, other: &Keep<T, A>) -> bool
+    {
+        let other_ta = other.cell().tracked_atomic.load(Ordering::Acquire);
+
+        if let Ok(self_ta) = self.cell().tracked_atomic.compare_exchange(
+            marker.0,
+            other_ta,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        )
         {
-            unsafe {
-                other
-                    .tracked_atomic
-                    .load(Ordering::Acquire)
-                    .as_ref()
-                    .unwrap()
-                    .store(self_ta, Ordering::Release)
-            };
+            other
+                .cell()
+                .tracked_atomic
+                .store(self_ta, Ordering::Release);
 
             return true;
         }
@@ -172,79 +353,270 @@ impl<T> Keep<T>
     }
 
     /// Clones `other` into `self` and returns the "old" `self`
-    pub fn clone_from(&self, other: &Keep<T>) -> Self
+    pub fn clone_from(&self, other: &Keep<T, A>) -> Self
     {
         // Read the tracked atomic from other
-        let other_ta = unsafe {
-            other
-                .tracked_atomic
-                .load(Ordering::Acquire)
-                .as_ref()
-                .unwrap()
-                .load(Ordering::Acquire)
-        };
+        let other_ta = other.cell().tracked_atomic.load(Ordering::Acquire);
 
         // Increase the accessor count of other
         unsafe { &*other_ta }.register_accessor();
 
-        // Replace the "old" tracked atomic in self with other
-        let old = self.tracked_atomic.swap(
-            other.tracked_atomic.load(Ordering::Acquire),
-            Ordering::SeqCst,
-        );
+        // Share the cell of other, the "old" cell of self is handed over to the returned Keep
+        other.cell().handles.fetch_add(1, Ordering::SeqCst);
+        let old = self
+            .cell
+            .swap(other.cell.load(Ordering::Acquire), Ordering::SeqCst);
 
         Keep {
-            tracked_atomic: AtomicPtr::new(old),
+            cell: AtomicPtr::new(old),
+        }
+    }
+
+    /// Returns the current value if `self` is the only handle to it, otherwise `self` is returned.
+    ///
+    /// A `Keep` is unique if there are no clones of it and no `Guard`s to any of its values.
+    pub fn try_unwrap(self) -> Result<T, Self>
+    {
+        match self.tracked_atomic().try_take()
+        {
+            Some(val) => Ok(val),
+            None => Err(self),
         }
     }
 
+    /// Consumes `self` and returns a guard to its current value.
+    ///
+    /// The value stays alive for as long as the guard does, even if `self` was the last `Keep`.
+    /// A `Keep` created by `new_immutable` hands its accessor registration over to the guard, which
+    /// skips registering and unregistering an accessor. Other values might be replaced through
+    /// clones of `self`, so their guards are registered like the ones returned by `read`.
+    pub fn into_guard(self) -> Guard<T, A>
+    {
+        let tracked_atomic = self.tracked_atomic();
+
+        if !tracked_atomic.is_immutable()
+        {
+            // The guard protects the value before `self` unregisters as an accessor.
+            return tracked_atomic.load();
+        }
+
+        let alloc = tracked_atomic.allocator();
+        let guard = unsafe { tracked_atomic.registered_guard() };
+        let this = ManuallyDrop::new(self);
+        unsafe { this.release_cell(alloc) };
+
+        guard
+    }
+
+    /// Panics if any `Guard` to the current or a replaced value of `self` is still alive.
+    ///
+    /// Meant for tests, to catch guards outliving their intended scope. Guards of a `Keep`
+    /// created by `new_immutable` are counted like clones of the `Keep` and are not detected.
+    /// Like `debug_assert!`, this only checks anything in builds with debug assertions enabled.
+    pub fn assert_no_outstanding_guards(&self)
+    {
+        debug_assert!(
+            !self.tracked_atomic().has_guards(),
+            "Keep still has outstanding guards"
+        );
+    }
+
+    /// Returns the address of the control block backing `self`.
+    ///
+    /// Clones of a `Keep` share their control block, so this can be used to key
+    /// containers by the identity of a `Keep` instead of its value.
+    pub fn as_ptr(&self) -> *const ()
+    {
+        self.tracked_atomic() as *const TrackedAtomic<T, A> as *const ()
+    }
+
+    /// Returns `true` if both `Keep`s are handles to the same cell, like a `Keep` and its clone.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool
+    {
+        this.as_ptr() == other.as_ptr()
+    }
+
+    /// Returns the tracked atomic currently backing `self`.
+    #[inline]
+    fn tracked_atomic(&self) -> &TrackedAtomic<T, A>
+    {
+        unsafe { &*self.cell().tracked_atomic.load(Ordering::Acquire) }
+    }
+
+    /// Returns the cell `self` shares with its clones.
+    #[inline]
+    fn cell(&self) -> &KeepCell<T, A>
+    {
+        unsafe { &*self.cell.load(Ordering::Acquire) }
+    }
+
     unsafe fn destroy(&self)
     {
-        let tracked_atomic = unsafe {
-            Heap::from_ptr(
-                self.tracked_atomic
-                    .load(Ordering::Acquire)
-                    .as_ref()
-                    .unwrap()
-                    .load(Ordering::Acquire),
-            )
+        let alloc = self.tracked_atomic().allocator();
+        let tracked_atomic = self.cell().tracked_atomic.load(Ordering::Acquire);
+
+        // Guards outliving `self` might see the tracked atomic die as well, see `TrackedAtomic::release`.
+        unsafe {
+            TrackedAtomic::release(Heap::from_ptr_in(tracked_atomic, alloc), |tracked_atomic| {
+                if tracked_atomic.unregister_accessor()
+                {
+                    tracked_atomic.dispose_if_dead();
+                }
+            })
         };
 
-        if tracked_atomic.unregister_accessor() && tracked_atomic.is_dead()
+        unsafe { self.release_cell(alloc) };
+    }
+
+    /// Releases the handle of `self` to its cell, without unregistering `self` as an accessor.
+    ///
+    /// The tracked atomic might already be gone, so its allocator `alloc` is passed in.
+    ///
+    /// # Safety
+    /// `self` must not be used afterwards.
+    unsafe fn release_cell(&self, alloc: A)
+    {
+        let cell = self.cell.load(Ordering::Acquire);
+
+        if unsafe { &*cell }.handles.fetch_sub(1, Ordering::AcqRel) == 1
         {
-            unsafe {
-                tracked_atomic.destroy();
-                tracked_atomic.free();
-                Heap::from_ptr(self.tracked_atomic.load(Ordering::Acquire)).free();
-            };
+            unsafe { Heap::from_ptr_in(cell, alloc).free() };
         }
     }
 }
 
 
-impl<T> Clone for Keep<T>
+impl<T, A: Allocator + Copy> Keep<Option<T>, A>
 {
-    fn clone(&self) -> Self
+    /// Stores `Some(val)` if the current value is `None` and returns whether it did.
+    ///
+    /// Of many threads racing to fill an empty slot, exactly one succeeds.
+    /// Returns `false` (dropping `val`) if the slot is already filled.
+    pub fn set_if_none(&self, val: T) -> bool
     {
-        unsafe {
-            Heap::from_ptr(
-                self.tracked_atomic
-                    .load(Ordering::Acquire)
-                    .as_ref()
-                    .unwrap()
-                    .load(Ordering::Acquire),
-            )
+        let tracked_atomic = self.tracked_atomic();
+        let mut current = tracked_atomic.load();
+        let mut val = Some(val);
+
+        // Concurrent writes that leave the slot empty, like storing `None`, don't fill it.
+        while current.is_none()
+        {
+            match tracked_atomic.exchange_or_return(&current, val)
+            {
+                Ok(_) => return true,
+                Err((actual, rejected)) =>
+                {
+                    current = actual;
+                    val = rejected;
+                }
+            }
         }
-        .register_accessor();
+
+        false
+    }
+}
+
+
+impl<T: Copy + PartialEq, A: Allocator + Copy> Keep<T, A>
+{
+    /// Stores `f(current)` unless it equals the current value, returns the previous value.
+    fn fetch_with(&self, f: impl Fn(T) -> T) -> T
+    {
+        let mut current = self.read();
+
+        loop
+        {
+            let new = f(*current);
+
+            // Leaving the value untouched does not allocate a new one.
+            if new == *current
+            {
+                return *current;
+            }
+
+            match self.exchange(&current, new)
+            {
+                Ok(old) => return *old,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+
+impl<A: Allocator + Copy> Keep<bool, A>
+{
+    /// Stores the logical or of the current value and `val`, returns the previous value.
+    pub fn fetch_or(&self, val: bool) -> bool
+    {
+        self.fetch_with(|current| current | val)
+    }
+
+    /// Stores the logical and of the current value and `val`, returns the previous value.
+    pub fn fetch_and(&self, val: bool) -> bool
+    {
+        self.fetch_with(|current| current & val)
+    }
+}
+
+
+impl<A: Allocator + Copy> Keep<usize, A>
+{
+    /// Adds `delta` to the current value, wrapping around on overflow, returns the previous value.
+    pub fn fetch_add(&self, delta: usize) -> usize
+    {
+        self.fetch_with(|current| current.wrapping_add(delta))
+    }
+
+    /// Subtracts `delta` from the current value, wrapping around on overflow, returns the previous value.
+    pub fn fetch_sub(&self, delta: usize) -> usize
+    {
+        self.fetch_with(|current| current.wrapping_sub(delta))
+    }
+}
+
+
+impl<T, A: Allocator + Copy> Clone for Keep<T, A>
+{
+    fn clone(&self) -> Self
+    {
+        self.tracked_atomic().register_accessor();
+        self.cell().handles.fetch_add(1, Ordering::SeqCst);
 
         Self {
-            tracked_atomic: AtomicPtr::new(self.tracked_atomic.load(Ordering::Acquire)),
+            cell: AtomicPtr::new(self.cell.load(Ordering::Acquire)),
         }
     }
 }
 
 
-impl<T> Drop for Keep<T>
+/// Hashes the current value of the `Keep`.
+///
+/// Since the hash depends on the current value, mutating a `Keep` while it is
+/// used as a key (e.g. in a `PlugMap`) is a logic error.
+impl<T: core::hash::Hash, A: Allocator + Copy> core::hash::Hash for Keep<T, A>
+{
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H)
+    {
+        self.read().hash(state);
+    }
+}
+
+
+/// Compares the current values of two `Keep`s.
+impl<T: PartialEq, A: Allocator + Copy> PartialEq for Keep<T, A>
+{
+    fn eq(&self, other: &Self) -> bool
+    {
+        *self.read() == *other.read()
+    }
+}
+
+
+impl<T: Eq, A: Allocator + Copy> Eq for Keep<T, A> {}
+
+
+impl<T, A: Allocator + Copy> Drop for Keep<T, A>
 {
     fn drop(&mut self)
     {
@@ -1,10 +1,64 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+
+// Renamed, since `alloc` is the name of the module wrapping the allocator API.
+extern crate alloc as alloc_crate;
+
+
 mod alist;
+mod alloc;
 mod guard;
 mod heaped;
 mod keep;
+mod retire;
 mod tracked_atomic;
 
+#[cfg(feature = "metrics")]
+pub mod metrics;
 
+pub mod double_buffer;
+pub mod local;
+pub mod shared;
+
+
+pub use alloc::{Allocator, Global};
 pub use guard::Guard;
 pub use heaped::{Heap, Heaped};
 pub use keep::Keep;
+pub use retire::quiesce;
+
+#[cfg(feature = "metrics")]
+pub use metrics::allocated_bytes;
+
+
+/// Handles which can be compared by identity, see `ptr_eq`.
+pub trait PtrEq
+{
+    fn ptr_eq(this: &Self, other: &Self) -> bool;
+}
+
+
+impl<T, A: Allocator + Copy> PtrEq for Guard<T, A>
+{
+    fn ptr_eq(this: &Self, other: &Self) -> bool
+    {
+        Guard::ptr_eq(this, other)
+    }
+}
+
+
+impl<T, A: Allocator + Copy> PtrEq for Keep<T, A>
+{
+    fn ptr_eq(this: &Self, other: &Self) -> bool
+    {
+        Keep::ptr_eq(this, other)
+    }
+}
+
+
+/// Returns `true` if `a` and `b` refer to the same value (for `Guard`s) or cell (for `Keep`s).
+pub fn ptr_eq<P: PtrEq>(a: &P, b: &P) -> bool
+{
+    P::ptr_eq(a, b)
+}
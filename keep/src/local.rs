@@ -0,0 +1,84 @@
+use alloc_crate::rc::Rc;
+use core::cell::RefCell;
+
+
+/// A single threaded `Keep`.
+///
+/// `Keep` tracks its readers with atomics, so values can be replaced while other threads read them.
+/// A `LocalKeep` can't leave its thread, so it uses plain reference counts instead, which makes reading
+/// and writing considerably cheaper. Clones share the same value cell, like clones of a `Keep`.
+pub struct LocalKeep<T>
+{
+    cell: Rc<RefCell<Rc<T>>>,
+}
+
+
+impl<T> LocalKeep<T>
+{
+    pub fn new(val: T) -> Self
+    {
+        Self {
+            cell: Rc::new(RefCell::new(Rc::new(val))),
+        }
+    }
+
+    /// Returns a guard to the current value, which stays alive for as long as the guard does.
+    pub fn read(&self) -> Rc<T>
+    {
+        self.cell.borrow().clone()
+    }
+
+    /// Replaces the current value with `val`.
+    pub fn write(&self, val: T)
+    {
+        self.swap(val);
+    }
+
+    /// Replaces the current value with `val` and returns a guard to the old value.
+    pub fn swap(&self, val: T) -> Rc<T>
+    {
+        self.cell.replace(Rc::new(val))
+    }
+
+    /// Replaces the current value with `f(current)` and returns a guard to the new value.
+    pub fn update_and_fetch(&self, f: impl FnOnce(&T) -> T) -> Rc<T>
+    {
+        let new = Rc::new(f(&self.read()));
+        *self.cell.borrow_mut() = new.clone();
+        new
+    }
+
+    /// Returns the current value if `self` is the only handle to it, otherwise `self` is returned.
+    ///
+    /// Like for `Keep::try_unwrap`, there may be no clones of `self` and no guards to its value.
+    pub fn try_unwrap(self) -> Result<T, Self>
+    {
+        let cell = Rc::try_unwrap(self.cell).map_err(|cell| Self { cell })?;
+
+        Rc::try_unwrap(cell.into_inner()).map_err(Self::from_rc)
+    }
+
+    /// Returns `true` if `this` and `other` share the same value cell.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool
+    {
+        Rc::ptr_eq(&this.cell, &other.cell)
+    }
+
+    fn from_rc(val: Rc<T>) -> Self
+    {
+        Self {
+            cell: Rc::new(RefCell::new(val)),
+        }
+    }
+}
+
+
+impl<T> Clone for LocalKeep<T>
+{
+    fn clone(&self) -> Self
+    {
+        Self {
+            cell: self.cell.clone(),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Allocation tracking, enabled by the `metrics` feature.
+//!
+//! Counts the bytes of all values, mutations and control blocks `keep` allocated and did not free yet.
+//! Values handed over as a `Box` or a raw `Heap` count as allocated by `keep`, since `keep` frees them.
+
+
+use core::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+
+
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+// usize::MAX means there is no limit.
+static LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+
+/// Returned by `Keep::try_new` if the allocation limit was reached, see `set_allocation_limit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded;
+
+
+/// Returns the number of bytes `keep` currently has allocated.
+///
+/// Replaced values are dropped right away, but their memory is released in batches once no reader
+/// of their `Keep` is pinned, so this includes the memory of some replaced values until then.
+pub fn allocated_bytes() -> usize
+{
+    ALLOCATED.load(Ordering::Relaxed).max(0) as usize
+}
+
+
+/// Sets a soft limit on `allocated_bytes`, above which `Keep::try_new` fails.
+///
+/// The limit is soft since it is only checked by `Keep::try_new`, other allocations
+/// (like writes) and concurrent `try_new`s can still exceed it.
+pub fn set_allocation_limit(limit: Option<usize>)
+{
+    LIMIT.store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+
+/// Returns `true` if the allocation limit has been reached.
+pub(crate) fn limit_exceeded() -> bool
+{
+    allocated_bytes() >= LIMIT.load(Ordering::Relaxed)
+}
+
+
+#[inline]
+pub(crate) fn allocated(bytes: usize)
+{
+    ALLOCATED.fetch_add(bytes as isize, Ordering::Relaxed);
+}
+
+
+#[inline]
+pub(crate) fn freed(bytes: usize)
+{
+    ALLOCATED.fetch_sub(bytes as isize, Ordering::Relaxed);
+}
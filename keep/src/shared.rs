@@ -0,0 +1,201 @@
+use crate::{
+    alloc::Global,
+    heaped::{Heap, Heaped},
+    tracked_atomic::TrackedAtomic,
+};
+use core::ops::Deref;
+
+
+/// A strong, `Arc`-like reference to an immutable value.
+///
+/// Strong references are the accessors of an immutable tracked atomic and weak references are counted
+/// next to them, so a `Shared` costs no more than a `Keep` created by `Keep::new_immutable`.
+/// The value is dropped once the last `Shared` is dropped, the control block
+/// is freed once the last `WeakShared` is dropped as well.
+pub struct Shared<T>
+{
+    tracked_atomic: Heap<TrackedAtomic<T>>,
+}
+
+
+/// A weak reference to a value owned by `Shared`s.
+///
+/// A `WeakShared` does not keep the value alive, use `WeakShared::upgrade` to access it.
+pub struct WeakShared<T>
+{
+    tracked_atomic: Heap<TrackedAtomic<T>>,
+}
+
+
+impl<T> Shared<T>
+{
+    pub fn new(val: impl Heaped<T>) -> Self
+    {
+        let tracked_atomic = TrackedAtomic::new_immutable(val, Global);
+        tracked_atomic.register_accessor();
+
+        Self { tracked_atomic }
+    }
+
+    /// Creates a new `WeakShared` pointing to the value of `this`.
+    pub fn downgrade(this: &Self) -> WeakShared<T>
+    {
+        this.tracked_atomic.downgrade();
+
+        WeakShared {
+            tracked_atomic: this.tracked_atomic,
+        }
+    }
+
+    /// Returns the number of `Shared`s pointing to the value of `this`.
+    pub fn strong_count(this: &Self) -> usize
+    {
+        this.tracked_atomic.accessor_count()
+    }
+
+    /// Returns the number of `WeakShared`s pointing to the value of `this`.
+    pub fn weak_count(this: &Self) -> usize
+    {
+        this.tracked_atomic.weak_count() - 1
+    }
+
+    /// Returns a mutable reference to the value if `this` is the only reference to it.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T>
+    {
+        if Self::strong_count(this) == 1 && Self::weak_count(this) == 0
+        {
+            // Nobody else can observe the value, since `this` is borrowed mutably.
+            return Some(unsafe { &mut *this.tracked_atomic.current_ptr_unchecked() });
+        }
+
+        None
+    }
+
+    /// Returns `true` if both `Shared`s point to the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool
+    {
+        this.tracked_atomic.as_ptr() == other.tracked_atomic.as_ptr()
+    }
+}
+
+
+impl<T> WeakShared<T>
+{
+    /// Tries to get a `Shared` to the value, returns `None` if the value was already dropped.
+    pub fn upgrade(&self) -> Option<Shared<T>>
+    {
+        // `then_some` would drop a `Shared` which was never registered as an accessor.
+        self.tracked_atomic.try_register_accessor().then(|| {
+            Shared {
+                tracked_atomic: self.tracked_atomic,
+            }
+        })
+    }
+
+    /// Returns the number of `Shared`s pointing to the value.
+    pub fn strong_count(&self) -> usize
+    {
+        self.tracked_atomic.accessor_count()
+    }
+
+    /// Returns the number of `WeakShared`s pointing to the value, or 0 if the value was dropped.
+    pub fn weak_count(&self) -> usize
+    {
+        match self.strong_count()
+        {
+            0 => 0,
+            _ => self.tracked_atomic.weak_count() - 1,
+        }
+    }
+}
+
+
+impl<T> Deref for Shared<T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target
+    {
+        // The value of an immutable tracked atomic is never replaced.
+        unsafe { self.tracked_atomic.current_unchecked() }
+    }
+}
+
+
+impl<T> AsRef<T> for Shared<T>
+{
+    fn as_ref(&self) -> &T
+    {
+        self
+    }
+}
+
+
+impl<T: core::fmt::Debug> core::fmt::Debug for Shared<T>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T: core::fmt::Display> core::fmt::Display for Shared<T>
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        (**self).fmt(f)
+    }
+}
+
+
+impl<T> Clone for Shared<T>
+{
+    fn clone(&self) -> Self
+    {
+        self.tracked_atomic.register_accessor();
+
+        Self {
+            tracked_atomic: self.tracked_atomic,
+        }
+    }
+}
+
+
+impl<T> Clone for WeakShared<T>
+{
+    fn clone(&self) -> Self
+    {
+        self.tracked_atomic.downgrade();
+
+        Self {
+            tracked_atomic: self.tracked_atomic,
+        }
+    }
+}
+
+
+impl<T> Drop for Shared<T>
+{
+    fn drop(&mut self)
+    {
+        // The last strong reference drops the value, and the weak reference all of them hold together.
+        unsafe {
+            TrackedAtomic::release(self.tracked_atomic, |tracked_atomic| {
+                if tracked_atomic.unregister_accessor()
+                {
+                    tracked_atomic.dispose_if_dead();
+                }
+            })
+        };
+    }
+}
+
+
+impl<T> Drop for WeakShared<T>
+{
+    fn drop(&mut self)
+    {
+        unsafe { TrackedAtomic::release_weak(self.tracked_atomic) };
+    }
+}
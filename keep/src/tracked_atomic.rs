@@ -1,19 +1,32 @@
 use crate::{
     alist::Node,
+    alloc::{Allocator, Global},
     guard::Guard,
     heaped::{Heap, Heaped},
 };
-use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use core::{
+    any::TypeId,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering, fence},
+};
 
 
-pub struct Mutation<T>
+// `Mutation`, `TrackedAtomic` and `Node` are `repr(C)` and only hold their `T` behind pointers, so their layout
+// is the same for every `T`. `Guard::downcast` relies on this to reinterpret them for another `T`.
+#[repr(C)]
+pub struct Mutation<T, A: Allocator + Copy = Global>
 {
-    ptr: Heap<T>,
-    freed: Heap<AtomicBool>, // This Flag will prevent double frees
+    ptr: Heap<T, A>,
+    freed: Heap<AtomicBool, A>, // This Flag will prevent double frees
+
+    // The number of mutations published before this one, assigned right before publishing.
+    version: u64,
+
+    // The next mutation retired to the same tracked atomic, see `TrackedAtomic::retire`.
+    next_retired: AtomicPtr<Mutation<T, A>>,
 }
 
 
-impl<T> Mutation<T>
+impl<T, A: Allocator + Copy> Mutation<T, A>
 {
     pub fn inner(&self) -> *mut T
     {
@@ -24,52 +37,241 @@ impl<T> Mutation<T>
     {
         &self.ptr
     }
+
+    /// Returns the version this mutation was published as.
+    #[inline]
+    pub fn version(&self) -> u64
+    {
+        self.version
+    }
 }
 
 
-impl<T> Mutation<T>
+impl<T, A: Allocator + Copy> Mutation<T, A>
 {
-    fn new(ptr: impl Heaped<T>) -> Heap<Self>
+    fn new(ptr: impl Heaped<T, A>, alloc: A) -> Heap<Self, A>
     {
         unsafe {
             Self {
-                ptr: ptr.heaped(),
-                freed: AtomicBool::new(false).heaped(),
+                ptr: ptr.heaped_in(alloc),
+                freed: AtomicBool::new(false).heaped_in(alloc),
+                version: 0,
+                next_retired: AtomicPtr::new(core::ptr::null_mut()),
             }
-            .heaped()
+            .heaped_in(alloc)
+        }
+    }
+
+    /// Drops and frees a mutation that was never published.
+    ///
+    /// # Safety
+    /// `mutation` must not be reachable from anywhere else.
+    unsafe fn discard(mutation: Heap<Self, A>)
+    {
+        unsafe {
+            mutation.ptr.free();
+            mutation.freed.free();
+            mutation.free();
+        }
+    }
+
+    /// Moves the value out of a mutation that was never published and frees the mutation.
+    ///
+    /// # Safety
+    /// `mutation` must not be reachable from anywhere else.
+    unsafe fn into_value(mutation: Heap<Self, A>) -> T
+    {
+        unsafe {
+            let val = mutation.ptr.take();
+            mutation.freed.free();
+            mutation.free();
+            val
         }
     }
+
+    /// Frees a retired mutation, along with the memory of its already dropped value.
+    ///
+    /// # Safety
+    /// `ptr` must point to a retired `Mutation<T, A>`, which is not used again after this call.
+    unsafe fn free_retired(ptr: *mut u8)
+    {
+        let mutation = ptr as *mut Self;
+
+        unsafe {
+            let alloc = (*mutation).freed.allocator();
+            (*mutation).ptr.dealloc();
+            (*mutation).freed.free();
+            Heap::from_ptr_in(mutation, alloc).free();
+        }
+    }
+
+    /// Frees a chain of retired mutations linked by `next_retired` and returns its length.
+    ///
+    /// # Safety
+    /// Same as `free_retired`, for every mutation of the chain.
+    unsafe fn free_chain(mut current: *mut Self) -> usize
+    {
+        let mut count = 0;
+
+        while !current.is_null()
+        {
+            let next = unsafe { (*current).next_retired.load(Ordering::Relaxed) };
+            unsafe { Self::free_retired(current as *mut u8) };
+
+            current = next;
+            count += 1;
+        }
+
+        count
+    }
 }
 
 
-pub struct TrackedAtomic<T>
+/// Number of retired mutations that are collected before their memory is freed in one batch.
+pub(crate) const RETIRE_BATCH: usize = 32;
+
+/// Set in the pin count of a dead tracked atomic, which the last thread to unpin it disposes.
+const DISPOSING: usize = 1 << (usize::BITS - 1);
+
+
+#[repr(C)]
+pub struct TrackedAtomic<T, A: Allocator + Copy = Global>
 {
     accessor_count: AtomicUsize,
-    mutation: AtomicPtr<Mutation<T>>,
-    freed: Heap<Node<AtomicBool>>,
-    domain: Heap<Node<Mutation<T>>>,
+
+    // Weak handles keeping `self` allocated but not its value alive, all accessors together hold one.
+    weak_count: AtomicUsize,
+
+    mutation: AtomicPtr<Mutation<T, A>>,
+    domain: Heap<Node<Mutation<T, A>, A>, A>,
+
+    // Mutations with dropped values whose memory was not yet freed, see `TrackedAtomic::retire`.
+    retired: AtomicPtr<Mutation<T, A>>,
+    retired_count: AtomicUsize,
+
+    // Threads touching a mutation without a guard protecting it, see `TrackedAtomic::pinned`,
+    // along with the `DISPOSING` flag.
+    pins: AtomicUsize,
+
+    // Values of immutable tracked atomics are never replaced, so their guards skip the domain.
+    immutable: bool,
+
+    // The concrete type behind the boxed value, set by `Keep::new_tagged`, along with a mutation whose
+    // value is the thin pointer to the boxed value, see `Guard::downcast`.
+    #[allow(clippy::type_complexity)]
+    tag: Option<(TypeId, Heap<Mutation<AtomicPtr<()>, A>, A>)>,
+
+    // Destroys and frees a tracked atomic as the type it was created as, see `TrackedAtomic::dispose`.
+    dispose: unsafe fn(*mut u8),
+
+    // The pointer `self` was allocated as, which unlike a pointer derived from `&self` may be used to free it.
+    this: AtomicPtr<Self>,
+    alloc: A,
 }
 
 
-impl<T> TrackedAtomic<T>
+impl<T, A: Allocator + Copy> TrackedAtomic<T, A>
 {
-    /// Creates a new tracked atomic initialized to `value`
-    pub fn new(value: impl Heaped<T>) -> Heap<Self>
+    /// Creates a new tracked atomic initialized to `value`, allocating from `alloc`
+    pub fn new(value: impl Heaped<T, A>, alloc: A) -> Heap<Self, A>
     {
-        let mutation = Mutation::new(value);
-        let head = Node::new(mutation, None);
+        let mutation = Mutation::new(value, alloc);
+        let head = Node::new(mutation, None, alloc);
 
         head.clear_unchecked();
 
-        unsafe {
+        let tracked_atomic = unsafe {
             Self {
                 accessor_count: AtomicUsize::new(0),
+                weak_count: AtomicUsize::new(1),
                 mutation: AtomicPtr::new(mutation.as_ptr()),
                 domain: head,
-                freed: Node::new(Heap::from_ptr(std::ptr::null_mut()), None),
+                retired: AtomicPtr::new(core::ptr::null_mut()),
+                retired_count: AtomicUsize::new(0),
+                pins: AtomicUsize::new(0),
+                immutable: false,
+                tag: None,
+                dispose: Self::dispose_as_created,
+                this: AtomicPtr::new(core::ptr::null_mut()),
+                alloc,
             }
-            .heaped()
+            .heaped_in(alloc)
+        };
+
+        unsafe {
+            (*tracked_atomic.as_ptr())
+                .this
+                .store(tracked_atomic.as_ptr(), Ordering::Relaxed)
+        };
+        tracked_atomic
+    }
+
+    /// Creates a new tracked atomic whose value can never be replaced.
+    ///
+    /// Guards to its value count as accessors instead of being registered in the domain.
+    pub fn new_immutable(value: impl Heaped<T, A>, alloc: A) -> Heap<Self, A>
+    {
+        let tracked_atomic = Self::new(value, alloc);
+        unsafe { (*tracked_atomic.as_ptr()).immutable = true };
+        tracked_atomic
+    }
+
+    /// Creates a new immutable tracked atomic, whose boxed value is of the type with the `TypeId` `tag`.
+    ///
+    /// `data` returns the thin pointer to the boxed value, once the value is in place.
+    ///
+    /// # Safety
+    /// `tag` must be the `TypeId` of the concrete type of the value, see `Keep::new_tagged`.
+    pub unsafe fn new_tagged(
+        value: impl Heaped<T, A>,
+        tag: TypeId,
+        data: impl FnOnce(&T) -> *mut (),
+        alloc: A,
+    ) -> Heap<Self, A>
+    {
+        let tracked_atomic = Self::new_immutable(value, alloc);
+
+        unsafe {
+            let data = data((*tracked_atomic.mutation.load(Ordering::Relaxed)).borrow());
+            (*tracked_atomic.as_ptr()).tag =
+                Some((tag, Mutation::new(AtomicPtr::new(data), alloc)));
         }
+
+        tracked_atomic
+    }
+
+    /// Returns the tag of the value, if `self` was created by `new_tagged`.
+    #[inline]
+    pub fn tag(&self) -> Option<TypeId>
+    {
+        self.tag.map(|(tag, _)| tag)
+    }
+
+    /// Returns the mutation holding the thin pointer to the boxed value, if `self` was created by `new_tagged`.
+    #[inline]
+    pub fn tagged_view(&self) -> Option<Heap<Mutation<AtomicPtr<()>, A>, A>>
+    {
+        self.tag.map(|(_, view)| view)
+    }
+
+    /// Returns the allocator backing this tracked atomic
+    #[inline]
+    pub fn allocator(&self) -> A
+    {
+        self.alloc
+    }
+
+    /// Returns a `Heap` pointing to `self`.
+    #[inline]
+    fn as_heap(&self) -> Heap<Self, A>
+    {
+        unsafe { Heap::from_ptr_in(self.this.load(Ordering::Relaxed), self.alloc) }
+    }
+
+    /// Preallocates the domain, so `readers` guards can be alive at once without allocating.
+    pub fn reserve_readers(&self, readers: usize)
+    {
+        self.domain.reserve(readers);
     }
 
     /// Registers a new accessor of this tracked atomic
@@ -86,47 +288,357 @@ impl<T> TrackedAtomic<T>
         self.accessor_count.fetch_sub(1, Ordering::SeqCst) == 1
     }
 
-    pub fn store(&self, new_value: impl Heaped<T>)
+    /// Runs `f` while retired mutations are left alone, for code touching a mutation it does not guard.
+    ///
+    /// Retired mutations are freed once no thread is pinned anymore, see `flush_retired`.
+    /// The caller must hold a handle to `self` throughout, see `release` for giving one up.
+    pub fn pinned<R>(&self, f: impl FnOnce() -> R) -> R
+    {
+        self.pin();
+        let result = f();
+        self.flush_if_full();
+        self.pins.fetch_sub(1, Ordering::Release);
+
+        result
+    }
+
+    /// Runs `f`, which gives up a handle to `this`, pinned like `pinned` and disposes `this` if it died.
+    ///
+    /// Every thread which might see `this` die does so in `f` and marks it with `dispose_if_dead`,
+    /// then the last thread to unpin disposes it. So `this` is disposed exactly once, and only after
+    /// all threads releasing it concurrently are done with it.
+    ///
+    /// # Safety
+    /// `this` must not be used after this call, unless the caller holds another handle to it.
+    pub unsafe fn release(this: Heap<Self, A>, f: impl FnOnce(&Self))
+    {
+        // No function borrowing `this` may still be running once another thread disposes it.
+        let tracked_atomic = unsafe { &*this.as_ptr() };
+
+        tracked_atomic.pin();
+        f(tracked_atomic);
+        tracked_atomic.flush_if_full();
+
+        if tracked_atomic.pins.fetch_sub(1, Ordering::AcqRel) == DISPOSING | 1
+        {
+            unsafe { Self::dispose(this) };
+        }
+    }
+
+    /// Returns the number of accessors, which for a tracked atomic of `Shared`s are its strong handles.
+    pub fn accessor_count(&self) -> usize
     {
-        let new_value = Mutation::new(new_value);
-        let old_value = self.mutation.swap(new_value.as_ptr(), Ordering::AcqRel);
-        self.try_drop(unsafe { Heap::from_ptr(old_value) });
+        self.accessor_count.load(Ordering::SeqCst)
     }
 
-    pub fn load(&self) -> Guard<T>
+    /// Registers a new accessor, unless the last one is already gone, returns `false` in that case.
+    ///
+    /// Needs a weak handle to keep `self` allocated, see `downgrade`.
+    pub fn try_register_accessor(&self) -> bool
     {
-        let ptr = unsafe { Heap::from_ptr(self.mutation.load(Ordering::Acquire)) };
-        let node = self.domain.insert(ptr);
+        let mut accessors = self.accessor_count.load(Ordering::SeqCst);
 
-        Guard {
-            ptr,
-            node,
-            // NOTE: This assumes that self is being stored on the heap.
-            tracked_atomic: unsafe { Heap::from_ptr(self as *const _ as _) },
+        loop
+        {
+            if accessors == 0
+            {
+                return false;
+            }
+
+            match self.accessor_count.compare_exchange(
+                accessors,
+                accessors + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            {
+                Ok(_) => return true,
+                Err(actual) => accessors = actual,
+            }
         }
     }
 
-    pub fn swap(&self, new_value: impl Heaped<T>) -> Guard<T>
+    /// Registers a weak handle, which keeps `self` allocated once it was disposed, but not its value alive.
+    pub fn downgrade(&self)
+    {
+        self.weak_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns the number of weak handles, including the one all accessors hold together while any is alive.
+    pub fn weak_count(&self) -> usize
+    {
+        self.weak_count.load(Ordering::SeqCst)
+    }
+
+    /// Drops a weak handle of `this`, freeing it if it was the last one and `this` was already disposed.
+    ///
+    /// # Safety
+    /// `this` must not be used after this call, unless the caller holds another handle to it.
+    pub unsafe fn release_weak(this: Heap<Self, A>)
     {
-        let new_value = Mutation::new(new_value);
-        let old_value = self.mutation.swap(new_value.as_ptr(), Ordering::AcqRel);
-        let old_value = unsafe { Heap::from_ptr(old_value) };
+        if this.weak_count.fetch_sub(1, Ordering::AcqRel) == 1
+        {
+            unsafe { this.free() };
+        }
+    }
+
+    /// Marks `self` to be disposed once no thread is pinned anymore, if it is dead, see `release`.
+    pub fn dispose_if_dead(&self)
+    {
+        // A `Keep` unregistering and a guard clearing its node concurrently must not both miss
+        // the other one giving up its handle, so at least one of them sees `self` die.
+        fence(Ordering::SeqCst);
+
+        if self.is_dead()
+        {
+            self.pins.fetch_or(DISPOSING, Ordering::SeqCst);
+        }
+    }
+
+    #[inline]
+    fn pin(&self)
+    {
+        self.pins.fetch_add(1, Ordering::SeqCst);
+
+        // Pairs with the fence in `flush_retired`: either that flush sees this pin, or the pinned
+        // thread only sees the successors of the mutations the flush is about to free.
+        fence(Ordering::SeqCst);
+    }
+
+    /// Flushes the retired mutations once a batch is full, for a pinned thread done touching mutations.
+    #[inline]
+    fn flush_if_full(&self)
+    {
+        if self.retired_count.load(Ordering::Relaxed) >= RETIRE_BATCH
+        {
+            self.flush_retired(1);
+        }
+    }
+
+    /// Returns the number of values published after the initial one.
+    #[inline]
+    pub fn version(&self) -> u64
+    {
+        self.pinned(|| unsafe { (*self.mutation.load(Ordering::Acquire)).version })
+    }
+
+    /// Returns a reference to the current value without protecting it, see `Keep::borrow_unchecked`.
+    ///
+    /// # Safety
+    /// No other value may be published while the returned reference is alive.
+    #[inline]
+    pub unsafe fn current_unchecked(&self) -> &T
+    {
+        unsafe { (*self.mutation.load(Ordering::Acquire)).borrow() }
+    }
+
+    /// Returns a pointer to the current value without protecting it, like `current_unchecked`.
+    ///
+    /// # Safety
+    /// Same as `current_unchecked`.
+    #[inline]
+    pub unsafe fn current_ptr_unchecked(&self) -> *mut T
+    {
+        unsafe { (*self.mutation.load(Ordering::Acquire)).inner() }
+    }
+
+    /// Copies the current value out without registering a guard for it.
+    ///
+    /// A replaced value is dropped right away, but its memory is only freed by `flush_retired`,
+    /// which leaves it alone while this is pinned. Dropping a `Copy` value does nothing,
+    /// so the copy always sees the complete value.
+    pub fn get(&self) -> T
+    where
+        T: Copy,
+    {
+        self.pinned(|| unsafe { *(*self.mutation.load(Ordering::Acquire)).inner() })
+    }
+
+    pub fn store(&self, new_value: impl Heaped<T, A>)
+    {
+        let new_value = Mutation::new(new_value, self.alloc);
+
+        // The replaced mutation is not guarded, so a guard to it could drop it concurrently.
+        // A `Keep` is alive while storing, so `self` can't die here.
+        self.pinned(|| {
+            let old_value = self.publish(new_value);
+            self.try_drop(old_value);
+        });
+    }
+
+    /// Panics if `self` is immutable, since its guards would not protect a replaced value.
+    #[inline]
+    fn assert_mutable(&self)
+    {
+        assert!(
+            !self.immutable,
+            "tried to replace the value of an immutable Keep"
+        );
+    }
+
+    /// Publishes `new_value` as the successor of the current mutation and returns the replaced mutation.
+    ///
+    /// Must be called while pinned, since the current mutation is read without a guard.
+    fn publish(&self, new_value: Heap<Mutation<T, A>, A>) -> Heap<Mutation<T, A>, A>
+    {
+        self.assert_mutable();
+        let mut current = self.mutation.load(Ordering::Acquire);
+
+        loop
+        {
+            // new_value is not published yet, so it can still be written to.
+            unsafe { (*new_value.as_ptr()).version = (*current).version + 1 };
+
+            match self.mutation.compare_exchange_weak(
+                current,
+                new_value.as_ptr(),
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            {
+                Ok(old) => return unsafe { Heap::from_ptr_in(old, self.alloc) },
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Returns whether the value of `self` is never replaced, see `new_immutable`.
+    #[inline]
+    pub fn is_immutable(&self) -> bool
+    {
+        self.immutable
+    }
+
+    /// Returns a guard to the value of an immutable `self`, which takes over an accessor registration.
+    ///
+    /// # Safety
+    /// `self` must be immutable and the caller must hand over one of its accessor registrations,
+    /// the guard unregisters it once it is dropped.
+    pub unsafe fn registered_guard(&self) -> Guard<T, A>
+    {
+        debug_assert!(self.immutable);
 
         Guard {
-            ptr: old_value,
-            node: self.domain.insert(old_value),
-            tracked_atomic: unsafe { Heap::from_ptr(self as *const _ as _) },
+            ptr: unsafe { Heap::from_ptr_in(self.mutation.load(Ordering::Acquire), self.alloc) },
+            node: unsafe { Heap::from_ptr_in(core::ptr::null_mut(), self.alloc) },
+            tracked_atomic: self.as_heap(),
+        }
+    }
+
+    pub fn load(&self) -> Guard<T, A>
+    {
+        if self.immutable
+        {
+            self.register_accessor();
+            return unsafe { self.registered_guard() };
+        }
+
+        // The loaded mutation is only protected once the guard to it was validated below.
+        self.pinned(|| {
+            let ptr =
+                unsafe { Heap::from_ptr_in(self.mutation.load(Ordering::Acquire), self.alloc) };
+
+            let mut guard = Guard {
+                ptr,
+                node: self.domain.insert(ptr),
+                tracked_atomic: self.as_heap(),
+            };
+
+            // A store racing the insert above might have missed it and dropped the value already,
+            // so the guard only protects the value if it is still current after the insert.
+            loop
+            {
+                fence(Ordering::SeqCst);
+                let current = self.mutation.load(Ordering::Acquire);
+
+                if current == guard.ptr.as_ptr()
+                {
+                    return guard;
+                }
+
+                let ptr = unsafe { Heap::from_ptr_in(current, self.alloc) };
+
+                guard = Guard {
+                    ptr,
+                    node: self.domain.insert(ptr),
+                    tracked_atomic: self.as_heap(),
+                };
+            }
+        })
+    }
+
+    pub fn swap(&self, new_value: impl Heaped<T, A>) -> Guard<T, A>
+    {
+        self.assert_mutable();
+        let new_value = Mutation::new(new_value, self.alloc);
+
+        // The old value is only replaced while guarded, otherwise a guard to it which is dropped
+        // concurrently could free it before the returned guard is registered.
+        loop
+        {
+            let current = self.load();
+
+            // new_value is not published yet, so it can still be written to.
+            unsafe { (*new_value.as_ptr()).version = current.ptr.version + 1 };
+
+            if self
+                .mutation
+                .compare_exchange(
+                    current.ptr.as_ptr(),
+                    new_value.as_ptr(),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                )
+                .is_ok()
+            {
+                return current;
+            }
         }
     }
 
     pub fn exchange(
         &self,
-        current: &Guard<T>,
-        new_value: impl Heaped<T>,
-    ) -> Result<Guard<T>, Guard<T>>
+        current: &Guard<T, A>,
+        new_value: impl Heaped<T, A>,
+    ) -> Result<Guard<T, A>, Guard<T, A>>
     {
-        let new_value = Mutation::new(new_value);
-        let tracked_atomic = unsafe { Heap::from_ptr(self as *const _ as _) };
+        self.exchange_mutation(current, Mutation::new(new_value, self.alloc))
+            .map_err(|(actual, new_value)| {
+                // new_value was never published, so nobody else can hold a reference to it.
+                unsafe { Mutation::discard(new_value) };
+                actual
+            })
+    }
+
+    /// Like `exchange`, but hands `new_value` back on failure instead of dropping it.
+    pub fn exchange_or_return(
+        &self,
+        current: &Guard<T, A>,
+        new_value: T,
+    ) -> Result<Guard<T, A>, (Guard<T, A>, T)>
+    {
+        self.exchange_mutation(current, Mutation::new(new_value, self.alloc))
+            .map_err(|(actual, new_value)| {
+                // new_value was never published, so nobody else can hold a reference to it.
+                (actual, unsafe { Mutation::into_value(new_value) })
+            })
+    }
+
+    /// Publishes `new_value` if `current` is still the current value, see `exchange`.
+    ///
+    /// On failure the unpublished `new_value` is returned along with a guard to the actual value.
+    #[allow(clippy::type_complexity)]
+    fn exchange_mutation(
+        &self,
+        current: &Guard<T, A>,
+        new_value: Heap<Mutation<T, A>, A>,
+    ) -> Result<Guard<T, A>, (Guard<T, A>, Heap<Mutation<T, A>, A>)>
+    {
+        self.assert_mutable();
+        let tracked_atomic = self.as_heap();
+
+        // new_value is not published yet, so it can still be written to.
+        unsafe { (*new_value.as_ptr()).version = current.ptr.version + 1 };
 
         self.mutation
             .compare_exchange(
@@ -136,7 +648,7 @@ impl<T> TrackedAtomic<T>
                 Ordering::Acquire,
             )
             .map(|old| {
-                let old = unsafe { Heap::from_ptr(old) };
+                let old = unsafe { Heap::from_ptr_in(old, self.alloc) };
 
                 Guard {
                     ptr: old,
@@ -144,33 +656,215 @@ impl<T> TrackedAtomic<T>
                     tracked_atomic,
                 }
             })
-            .map_err(|actual| {
-                let actual = unsafe { Heap::from_ptr(actual) };
-
-                Guard {
-                    ptr: actual,
-                    node: self.domain.insert(actual),
-                    tracked_atomic,
-                }
+            .map_err(|_| {
+                // The value that failed the exchange might already be replaced and freed again,
+                // so a guard to it can't be made without checking it is still current, like `load` does.
+                (self.load(), new_value)
             })
     }
 
-    fn drop_mutation(&self, mutation: &Mutation<T>) -> bool
+    /// Like `exchange`, but returns a guard to `new_value` on success instead of the old value.
+    pub fn exchange_fetch(
+        &self,
+        current: &Guard<T, A>,
+        new_value: impl Heaped<T, A>,
+    ) -> Result<Guard<T, A>, Guard<T, A>>
+    {
+        self.assert_mutable();
+        let new_value = Mutation::new(new_value, self.alloc);
+        unsafe { (*new_value.as_ptr()).version = current.ptr.version + 1 };
+
+        // Protect new_value before publishing it, otherwise it could be reclaimed
+        // by a concurrent store before the returned guard exists.
+        let node = self.domain.insert(new_value);
+
+        match self.mutation.compare_exchange(
+            current.ptr.as_ptr(),
+            new_value.as_ptr(),
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        )
+        {
+            Ok(old) =>
+            {
+                // old is still guarded by current, and a `Keep` is alive while exchanging.
+                self.try_drop(unsafe { Heap::from_ptr_in(old, self.alloc) });
+
+                Ok(Guard {
+                    ptr: new_value,
+                    node,
+                    tracked_atomic: self.as_heap(),
+                })
+            }
+
+            Err(_) =>
+            {
+                node.clear(new_value.as_ptr());
+                unsafe { Mutation::discard(new_value) };
+
+                // See `exchange`.
+                Err(self.load())
+            }
+        }
+    }
+
+    /// Replaces the current value with `new_value`, but only if the version of the current value is `expected`.
+    ///
+    /// Returns a guard to the old value on success, or the version of the current value otherwise.
+    pub fn replace_if_version(
+        &self,
+        expected: u64,
+        new_value: impl Heaped<T, A>,
+    ) -> Result<Guard<T, A>, u64>
+    {
+        let current = self.load();
+
+        if current.ptr.version != expected
+        {
+            return Err(current.ptr.version);
+        }
+
+        // Versions only grow, so any other current value has a different version.
+        self.exchange(&current, new_value)
+            .map_err(|actual| actual.ptr.version)
+    }
+
+    /// Moves the current value out of `self` if there is exactly one accessor and no live guards.
+    ///
+    /// On success the value is no longer owned by `self`, so dropping the last accessor won't drop it.
+    pub fn try_take(&self) -> Option<T>
+    {
+        // A downcast `Keep` would move its value out as the wrong type, see `Guard::downcast`.
+        if self.tag.is_some()
+        {
+            return None;
+        }
+
+        if self.accessor_count.load(Ordering::SeqCst) != 1 || !self.domain.is_all_empty()
+        {
+            return None;
+        }
+
+        let mutation =
+            unsafe { Heap::from_ptr_in(self.mutation.load(Ordering::Acquire), self.alloc) };
+
+        if mutation
+            .freed
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
+        }
+
+        // The memory of the value is freed along with the mutation.
+        let val = unsafe { core::ptr::read(mutation.inner()) };
+        self.retire(mutation);
+        Some(val)
+    }
+
+    fn drop_mutation(&self, mutation: Heap<Mutation<T, A>, A>) -> bool
     {
         if mutation
             .freed
             .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
             .is_ok()
         {
-            unsafe { mutation.ptr.free() };
-            self.freed.insert(mutation.freed);
+            // Without drop glue there is nothing to do, and skipping it keeps the value untouched for
+            // concurrent `get`s still copying it.
+            if core::mem::needs_drop::<T>()
+            {
+                unsafe { core::ptr::drop_in_place(mutation.inner()) };
+            }
+
+            self.retire(mutation);
             return true;
         }
 
         false
     }
 
-    pub fn try_drop(&self, val: Heap<Mutation<T>>)
+    /// Defers freeing a mutation whose value was dropped or moved out, until `RETIRE_BATCH` mutations
+    /// were retired and no thread is pinned.
+    ///
+    /// Other threads might still check the `freed` flag of the mutation, see `pinned`. Freeing in
+    /// batches also reduces the pressure on the allocator under churn.
+    fn retire(&self, mutation: Heap<Mutation<T, A>, A>)
+    {
+        let mutation = mutation.as_ptr();
+
+        // Count the mutation before it can be taken, so a concurrent flush never underflows the count.
+        self.retired_count.fetch_add(1, Ordering::SeqCst);
+        unsafe { self.push_retired(mutation, mutation) };
+    }
+
+    /// Pushes the chain of retired mutations from `first` to `last` onto the retired list.
+    ///
+    /// # Safety
+    /// `last` must be reachable from `first` through `next_retired`, and the chain must not be on the list.
+    unsafe fn push_retired(&self, first: *mut Mutation<T, A>, last: *mut Mutation<T, A>)
+    {
+        let mut head = self.retired.load(Ordering::Acquire);
+
+        loop
+        {
+            unsafe { (*last).next_retired.store(head, Ordering::Relaxed) };
+
+            match self.retired.compare_exchange_weak(
+                head,
+                first,
+                Ordering::Release,
+                Ordering::Acquire,
+            )
+            {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// Frees all retired mutations, unless a thread besides the caller holding `own_pins` pins is pinned.
+    ///
+    /// The pins of the caller must not touch a mutation anymore. If another thread is pinned,
+    /// the retired mutations are left in place for a later flush.
+    fn flush_retired(&self, own_pins: usize)
+    {
+        if self.pins.load(Ordering::SeqCst) != own_pins
+        {
+            return;
+        }
+
+        let retired = self.retired.swap(core::ptr::null_mut(), Ordering::AcqRel);
+
+        if retired.is_null()
+        {
+            return;
+        }
+
+        // See `pinned`.
+        fence(Ordering::SeqCst);
+
+        // A thread pinned before the mutations were taken might still look at them, so put them back.
+        if self.pins.load(Ordering::Acquire) != own_pins
+        {
+            let mut last = retired;
+
+            while let Some(next) = unsafe { (*last).next_retired.load(Ordering::Relaxed).as_mut() }
+            {
+                last = next;
+            }
+
+            unsafe { self.push_retired(retired, last) };
+            return;
+        }
+
+        let freed = unsafe { Mutation::free_chain(retired) };
+        self.retired_count.fetch_sub(freed, Ordering::SeqCst);
+    }
+
+    /// Drops `val` unless it is current or guarded.
+    ///
+    /// Must be called while pinned, unless the caller still guards `val`.
+    pub fn try_drop(&self, val: Heap<Mutation<T, A>, A>)
     {
         let accessors = self.accessor_count.load(Ordering::SeqCst);
 
@@ -185,17 +879,9 @@ impl<T> TrackedAtomic<T>
             // All Keeps are dead
             match self.domain.contains_or_empty(val.as_ptr())
             {
-                Some(false) =>
-                {
-                    self.drop_mutation(&val);
-                }
-
-                None =>
+                Some(false) | None =>
                 {
-                    if self.drop_mutation(&val)
-                    {
-                        unsafe { self.destroy() };
-                    }
+                    self.drop_mutation(val);
                 }
 
                 _ => (),
@@ -204,29 +890,65 @@ impl<T> TrackedAtomic<T>
         // Some Keep is still alive, so just try to free the value...
         else if !self.domain.contains(val.as_ptr())
         {
-            self.drop_mutation(&val);
+            self.drop_mutation(val);
         }
     }
 
+    /// Returns `true` if any guard to a value of this tracked atomic is registered in its domain.
+    pub fn has_guards(&self) -> bool
+    {
+        !self.domain.is_all_empty()
+    }
+
     pub fn is_dead(&self) -> bool
     {
         self.accessor_count.load(Ordering::SeqCst) == 0 && self.domain.is_all_empty()
     }
 
-    pub unsafe fn destroy(&self)
+    /// Destroys `this` and frees it, unless a weak handle keeps it allocated, see `release_weak`.
+    ///
+    /// Unlike calling `destroy` and `free` directly, this frees `this` as the type it was created as,
+    /// even if it is reached through a guard returned by `Guard::downcast`.
+    ///
+    /// # Safety
+    /// `this` must be dead, no thread may be pinned and `this` must not be used again after this call.
+    unsafe fn dispose(this: Heap<Self, A>)
+    {
+        unsafe { ((*this.as_ptr()).dispose)(this.as_ptr() as *mut u8) }
+    }
+
+    /// # Safety
+    /// `ptr` must point to a dead `TrackedAtomic<T, A>`, which is not used again after this call.
+    unsafe fn dispose_as_created(ptr: *mut u8)
+    {
+        let tracked_atomic = ptr as *mut Self;
+
+        unsafe {
+            (*tracked_atomic).destroy();
+            Self::release_weak(Heap::from_ptr_in(tracked_atomic, (*tracked_atomic).alloc));
+        }
+    }
+
+    unsafe fn destroy(&self)
     {
         // Free the mutation
         let mutation = self.mutation.load(Ordering::Acquire);
-        unsafe {
-            self.drop_mutation(&*mutation);
+        self.drop_mutation(unsafe { Heap::from_ptr_in(mutation, self.alloc) });
+
+        // No thread is pinned anymore, so nobody looks at the retired mutations.
+        let retired = self.retired.swap(core::ptr::null_mut(), Ordering::AcqRel);
+        self.retired_count.store(0, Ordering::SeqCst);
+        unsafe { Mutation::free_chain(retired) };
+
+        if let Some((_, view)) = self.tag
+        {
+            unsafe { Mutation::discard(view) };
         }
 
-        // Free the lists
+        // Free the list
         unsafe {
             self.domain.free_list();
-            self.freed.free_list_and_nodes();
             self.domain.free();
-            self.freed.free();
         }
     }
 }
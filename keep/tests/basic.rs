@@ -1,4 +1,8 @@
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
+
+
 use keep::*;
+use std::thread;
 
 
 struct Cat(&'static str);
@@ -53,3 +57,607 @@ fn drops()
     println!("Dropping Evil Yuumi");
     drop(guard2);
 }
+
+
+#[test]
+fn try_unwrap()
+{
+    let keep = Keep::new(String::from("Fleur"));
+    let clone = keep.clone();
+
+    let keep = keep.try_unwrap().unwrap_err();
+    drop(clone);
+
+    let guard = keep.read();
+    let keep = keep.try_unwrap().unwrap_err();
+    drop(guard);
+
+    assert_eq!(Some("Fleur".to_string()), keep.try_unwrap().ok());
+}
+
+
+#[test]
+fn update_and_fetch()
+{
+    let keep = Keep::new(0usize);
+    let mut threads = vec![];
+
+    for _ in 0..8
+    {
+        let keep = keep.clone();
+
+        threads.push(thread::spawn(move || {
+            let mut last = 0;
+
+            for _ in 0..1000
+            {
+                let new = *keep.update_and_fetch(|v| v + 1);
+                assert!(new > last);
+                last = new;
+            }
+        }));
+    }
+
+    for t in threads
+    {
+        t.join().unwrap();
+    }
+
+    assert_eq!(8000, *keep.read());
+}
+
+
+#[test]
+fn read_pinned()
+{
+    let keep = Keep::new(String::from("Briar"));
+    let (guard, ptr) = keep.read_pinned();
+
+    keep.write(String::from("Miku"));
+
+    assert_eq!("Briar", unsafe { &*ptr });
+    assert_eq!(&*guard as *const String, ptr);
+    drop(guard);
+}
+
+
+#[test]
+fn as_ptr()
+{
+    let a = Keep::new(39);
+    let b = Keep::new(39);
+    let clone = a.clone();
+
+    assert_eq!(a.as_ptr(), clone.as_ptr());
+    assert_ne!(a.as_ptr(), b.as_ptr());
+
+    a.write(3);
+    assert_eq!(a.as_ptr(), clone.as_ptr());
+}
+
+
+#[test]
+fn ptr_eq()
+{
+    let a = Keep::new(39);
+    let b = Keep::new(39);
+    let clone = a.clone();
+
+    assert!(keep::ptr_eq(&a, &clone));
+    assert!(!keep::ptr_eq(&a, &b));
+
+    let first = a.read();
+    let alias = first.clone();
+    let equal = b.read();
+
+    // Equal values in different cells are not the same value.
+    assert_eq!(first, equal);
+    assert!(keep::ptr_eq(&first, &alias));
+    assert!(!keep::ptr_eq(&first, &equal));
+
+    // Writing the same value again still creates a new one.
+    a.write(39);
+    assert!(!keep::ptr_eq(&first, &a.read()));
+    assert!(keep::ptr_eq(&first, &alias));
+}
+
+
+#[test]
+fn set_if_none()
+{
+    let slot = Keep::new(None);
+    let mut threads = vec![];
+
+    for t in 0..8
+    {
+        let slot = slot.clone();
+        threads.push(thread::spawn(move || slot.set_if_none(t)));
+    }
+
+    let won: Vec<_> = threads
+        .into_iter()
+        .enumerate()
+        .filter_map(|(t, thread)| thread.join().unwrap().then_some(t))
+        .collect();
+
+    assert_eq!(vec![slot.read().unwrap()], won);
+    assert!(!slot.set_if_none(39));
+}
+
+
+#[test]
+fn set_if_none_while_rewritten()
+{
+    let slot = Keep::new(None);
+
+    // Keeps replacing the empty value with another empty one, until the slot was filled.
+    let rewriter = {
+        let slot = slot.clone();
+
+        thread::spawn(move || {
+            loop
+            {
+                let current = slot.read();
+
+                if current.is_some()
+                {
+                    break;
+                }
+
+                let _ = slot.exchange(&current, None);
+            }
+        })
+    };
+
+    assert!(slot.set_if_none(39));
+    rewriter.join().unwrap();
+    assert_eq!(Some(39), *slot.read());
+}
+
+
+#[test]
+fn borrow_unchecked()
+{
+    let keep = Keep::new(vec![3, 9, 39]);
+
+    for round in 0..10
+    {
+        // SAFETY: Nothing is stored in `keep` while `borrowed` is alive.
+        let borrowed = unsafe { keep.borrow_unchecked() };
+
+        assert_eq!(*keep.read(), *borrowed);
+        assert_eq!(3 + round, borrowed.len());
+
+        keep.update_and_fetch(|vec| [vec.as_slice(), &[round]].concat());
+    }
+
+    assert_eq!(13, unsafe { keep.borrow_unchecked() }.len());
+}
+
+
+#[test]
+fn fetch_or_and()
+{
+    let flag = Keep::new(false);
+    let mut threads = vec![];
+
+    for _ in 0..8
+    {
+        let flag = flag.clone();
+        threads.push(thread::spawn(move || !flag.fetch_or(true)));
+    }
+
+    // Exactly one thread observed the flag flipping from false to true.
+    let flipped = threads
+        .into_iter()
+        .map(|thread| thread.join().unwrap())
+        .filter(|flipped| *flipped)
+        .count();
+
+    assert_eq!(1, flipped);
+    assert!(*flag.read());
+
+    assert!(flag.fetch_and(true));
+    assert!(flag.fetch_and(false));
+    assert!(!flag.fetch_and(true));
+    assert!(!*flag.read());
+}
+
+
+#[test]
+fn fetch_add_sub()
+{
+    let counter = Keep::new(0usize);
+    let mut threads = vec![];
+
+    for _ in 0..8
+    {
+        let counter = counter.clone();
+        threads.push(thread::spawn(move || {
+            for _ in 0..1000
+            {
+                counter.fetch_add(1);
+            }
+        }));
+    }
+
+    for thread in threads
+    {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(8000, counter.get());
+    assert_eq!(8000, counter.fetch_sub(39));
+    assert_eq!(7961, counter.fetch_add(0));
+    assert_eq!(7961, counter.get());
+}
+
+
+#[test]
+fn cow_update()
+{
+    let list = Keep::new(Vec::<i32>::new());
+    let before = list.read();
+    let mut threads = vec![];
+
+    for t in 0..8
+    {
+        let list = list.clone();
+        threads.push(thread::spawn(move || {
+            for i in 0..100
+            {
+                list.cow_update(|list| list.push(t * 100 + i));
+            }
+        }));
+    }
+
+    for thread in threads
+    {
+        thread.join().unwrap();
+    }
+
+    let mut after = list.read().to_vec();
+    after.sort();
+
+    assert_eq!((0..800).collect::<Vec<_>>(), after);
+
+    // Guards taken before keep observing the old value.
+    assert!(before.is_empty());
+}
+
+
+#[test]
+fn into_guard()
+{
+    let keep = Keep::new(String::from("Briar"));
+    let clone = keep.clone();
+
+    let guard = keep.into_guard();
+    clone.write(String::from("Miku"));
+    assert_eq!("Briar", *guard);
+
+    let guard = clone.into_guard();
+    assert_eq!("Miku", *guard);
+
+    let immutable = Keep::new_immutable(String::from("Yuumi"));
+    let clone = immutable.clone();
+
+    let guard = immutable.into_guard();
+    drop(clone);
+    assert_eq!("Yuumi", *guard);
+}
+
+
+#[test]
+fn write_returning()
+{
+    let keep = Keep::new(39);
+    let old = keep.write_returning(3);
+
+    assert_eq!(39, *old);
+    assert_eq!(3, *keep.read());
+    assert_eq!(3, *keep.write_returning(9));
+}
+
+
+#[test]
+fn version()
+{
+    let config = Keep::new("fast");
+    let seen = config.version();
+
+    assert!(config.read_if_changed(seen).is_none());
+
+    config.write("safe");
+    config.write("slow");
+
+    let (guard, version) = config.read_if_changed(seen).unwrap();
+    assert_eq!("slow", *guard);
+    assert_eq!(seen + 2, version);
+    assert!(config.read_if_changed(version).is_none());
+
+    assert_eq!("slow", *config.swap("fast"));
+    assert_eq!(seen + 3, config.version());
+}
+
+
+#[test]
+fn swap_with_concurrent_read()
+{
+    let keep = Keep::new(String::from("Briar"));
+    let other = Keep::new(String::from("Miku"));
+
+    let reader = {
+        let keep = keep.clone();
+
+        thread::spawn(move || {
+            for _ in 0..1000
+            {
+                let val = keep.read();
+                assert!(*val == "Briar" || *val == "Miku");
+            }
+        })
+    };
+
+    for _ in 0..1000
+    {
+        keep.swap_with(&other);
+    }
+
+    reader.join().unwrap();
+    assert_eq!("Briar", *keep.read());
+    assert_eq!("Miku", *other.read());
+}
+
+
+#[test]
+fn replace_if_version()
+{
+    let keep = Keep::new(0usize);
+    let mut threads = vec![];
+
+    for _ in 0..8
+    {
+        let keep = keep.clone();
+
+        threads.push(thread::spawn(move || {
+            for _ in 0..500
+            {
+                loop
+                {
+                    let (current, version) = keep.read_versioned();
+
+                    match keep.replace_if_version(version, *current + 1)
+                    {
+                        Ok(old) =>
+                        {
+                            assert_eq!(*current, *old);
+                            break;
+                        }
+                        Err(actual) => assert!(actual > version),
+                    }
+                }
+            }
+        }));
+    }
+
+    for t in threads
+    {
+        t.join().unwrap();
+    }
+
+    assert_eq!(4000, *keep.read());
+    assert_eq!(4000, keep.version());
+    assert_eq!(Err(4000), keep.replace_if_version(39, 0).map(|_| ()));
+}
+
+
+#[test]
+fn immutable()
+{
+    let keep = Keep::new_immutable(String::from("Briar"));
+    let clone = keep.clone();
+    let guard = keep.read();
+
+    drop(keep);
+    assert_eq!("Briar", *clone.read());
+
+    drop(clone);
+    assert_eq!("Briar", *guard.clone());
+}
+
+
+#[test]
+fn no_outstanding_guards()
+{
+    let keep = Keep::new(39);
+    keep.assert_no_outstanding_guards();
+
+    let guard = keep.read();
+    keep.write(3);
+    drop(guard);
+
+    let _ = keep.read();
+    keep.assert_no_outstanding_guards();
+}
+
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "outstanding guards")]
+fn outstanding_guards()
+{
+    let keep = Keep::new(39);
+    let clone = keep.clone();
+
+    // A guard to a replaced value is detected as well.
+    let _guard = clone.read();
+    clone.write(3);
+
+    keep.assert_no_outstanding_guards();
+}
+
+
+#[test]
+#[should_panic]
+fn immutable_write()
+{
+    let keep = Keep::new_immutable(39);
+    keep.write(3);
+}
+
+
+#[test]
+fn into_keep()
+{
+    let keep = Keep::new(39);
+    let guard = keep.read();
+    let other = guard.into_keep();
+
+    other.write(3);
+    assert_eq!(3, *keep.read());
+
+    drop(keep);
+    assert_eq!(3, *other.read());
+}
+
+
+#[test]
+fn get()
+{
+    let keep = Keep::new(39usize);
+    assert_eq!(39, keep.get());
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let keep = keep.clone();
+            thread::spawn(move || {
+                for _ in 0..1000
+                {
+                    // Values are replaced and retired while they are copied out.
+                    let val = keep.get();
+                    keep.write(val + 1);
+                    assert!(keep.get() > 39);
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads
+    {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(keep.get(), *keep.read());
+}
+
+
+// Timing dependent, so it is only run on demand with `cargo test -- --ignored`.
+#[test]
+#[ignore]
+fn get_is_cheaper_than_read()
+{
+    use std::{
+        hint::black_box,
+        time::{Duration, Instant},
+    };
+
+    // Returns the fastest of a few rounds, so a single hiccup does not decide the outcome.
+    fn fastest(f: impl Fn() -> usize) -> Duration
+    {
+        (0..20)
+            .map(|_| {
+                let start = Instant::now();
+
+                for _ in 0..2000
+                {
+                    black_box(f());
+                }
+
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    }
+
+    let keep = Keep::new(39usize);
+    assert!(fastest(|| keep.get()) < fastest(|| *keep.read()));
+}
+
+
+#[test]
+fn downcast()
+{
+    use std::any::TypeId;
+
+    trait Named
+    {
+        fn name(&self) -> String;
+    }
+
+    struct Dog(u32);
+
+    impl Named for Cat
+    {
+        fn name(&self) -> String
+        {
+            self.0.to_string()
+        }
+    }
+
+    impl Named for Dog
+    {
+        fn name(&self) -> String
+        {
+            format!("Dog {}", self.0)
+        }
+    }
+
+    let cat: Keep<Box<dyn Named>> =
+        unsafe { Keep::new_tagged(Box::new(Cat("Briar")), TypeId::of::<Cat>()) };
+    let dog: Keep<Box<dyn Named>> =
+        unsafe { Keep::new_tagged(Box::new(Dog(3)), TypeId::of::<Dog>()) };
+
+    let Err(cat) = cat.read().downcast::<Dog>()
+    else
+    {
+        panic!("a Cat was downcast to a Dog");
+    };
+    assert_eq!("Briar", cat.name());
+
+    let Err(dog_guard) = dog.read().downcast::<Cat>()
+    else
+    {
+        panic!("a Dog was downcast to a Cat");
+    };
+    assert_eq!("Dog 3", dog_guard.name());
+
+    let cat = cat.downcast::<Cat>().ok().unwrap();
+    let dog_guard = dog_guard.downcast::<Dog>().ok().unwrap();
+    drop(dog);
+
+    assert_eq!("Briar", cat.0);
+    assert_eq!(3, dog_guard.0);
+
+    let untagged: Keep<Box<dyn Named>> = Keep::new(Box::new(Dog(39)) as Box<dyn Named>);
+    assert!(untagged.read().downcast::<Dog>().is_err());
+}
+
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn custom_allocator()
+{
+    use std::alloc::System;
+
+    let keep = Keep::new_in(39, System);
+    let guard = keep.read();
+
+    keep.write(42);
+
+    assert_eq!(42, *keep.read());
+    assert_eq!(42, *keep.swap(Box::new_in(7, System)));
+    assert_eq!(7, *keep.read());
+    drop(keep);
+    assert_eq!(39, *guard);
+}
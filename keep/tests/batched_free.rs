@@ -0,0 +1,32 @@
+mod common;
+
+
+use common::DEALLOCS;
+use keep::*;
+use std::sync::atomic::Ordering;
+
+
+#[test]
+fn batched_free()
+{
+    let keep = Keep::new(0usize);
+    let mut bursts = vec![];
+
+    for i in 1..=1000
+    {
+        let before = DEALLOCS.load(Ordering::SeqCst);
+        keep.write(i);
+        bursts.push(DEALLOCS.load(Ordering::SeqCst) - before);
+    }
+
+    // Most writes don't free anything, the memory of the replaced values is freed in batches.
+    let quiet = bursts.iter().filter(|&&b| b == 0).count();
+    let largest = bursts.iter().copied().max().unwrap();
+
+    assert!(quiet > 900, "only {quiet} writes did not free anything");
+    assert!(
+        largest > 16,
+        "largest burst only freed {largest} allocations"
+    );
+    assert_eq!(1000, *keep.read());
+}
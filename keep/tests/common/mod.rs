@@ -0,0 +1,49 @@
+//! A global allocator counting allocations, shared by the allocation tests.
+//!
+//! Every test binary including this module uses the counting allocator,
+//! so each of them should only contain a single test to not skew the counts.
+
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::atomic::{AtomicIsize, AtomicUsize, Ordering},
+};
+
+
+/// Number of allocations which were not yet freed.
+pub static OUTSTANDING: AtomicIsize = AtomicIsize::new(0);
+
+/// Number of deallocations so far.
+pub static DEALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+
+thread_local! {
+    /// Number of allocations made by the current thread.
+    pub static THREAD_ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+
+struct Counting;
+
+
+unsafe impl GlobalAlloc for Counting
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8
+    {
+        OUTSTANDING.fetch_add(1, Ordering::SeqCst);
+        let _ = THREAD_ALLOCS.try_with(|allocs| allocs.set(allocs.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout)
+    {
+        OUTSTANDING.fetch_sub(1, Ordering::SeqCst);
+        DEALLOCS.fetch_add(1, Ordering::SeqCst);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
@@ -0,0 +1,48 @@
+use keep::double_buffer::*;
+use std::thread;
+
+
+#[test]
+fn flip()
+{
+    let buffer = DoubleBuffer::new(String::from("Briar"), String::new());
+
+    buffer.write_back(String::from("Miku"));
+    assert_eq!("Briar", *buffer.front());
+    assert_eq!("Miku", *buffer.back());
+
+    let old_front = buffer.front();
+    buffer.flip();
+
+    assert_eq!("Miku", *buffer.front());
+    assert_eq!("Briar", *buffer.back());
+    assert_eq!("Briar", *old_front);
+}
+
+
+#[test]
+fn flip_concurrent_read()
+{
+    let buffer = DoubleBuffer::new(0usize, 1usize);
+
+    let reader = {
+        let buffer = buffer.clone();
+
+        thread::spawn(move || {
+            for _ in 0..1000
+            {
+                let front = *buffer.front();
+                assert!(front == 0 || front == 1);
+            }
+        })
+    };
+
+    for _ in 0..1000
+    {
+        buffer.flip();
+    }
+
+    reader.join().unwrap();
+    assert_eq!(0, *buffer.front());
+    assert_eq!(1, *buffer.back());
+}
@@ -0,0 +1,51 @@
+mod common;
+
+
+use common::OUTSTANDING;
+use keep::*;
+use std::{sync::atomic::Ordering, thread};
+
+
+#[test]
+fn guard_outlives_keep()
+{
+    let before = OUTSTANDING.load(Ordering::SeqCst);
+
+    // The last guard frees the control block of a Keep dropped before it.
+    for _ in 0..100
+    {
+        let keep = Keep::new(String::from("Briar"));
+        let guard = keep.read();
+        keep.write(String::from("Miku"));
+        drop(keep);
+        assert_eq!("Briar", *guard);
+
+        let immutable = Keep::new_immutable(String::from("Yuumi"));
+        let immutable_guard = immutable.read();
+        drop(immutable);
+        assert_eq!("Yuumi", *immutable_guard);
+
+        // The guard takes over the accessor registration of the last handle.
+        let immutable_guard = Keep::new_immutable(String::from("Nami")).into_guard();
+        assert_eq!("Nami", *immutable_guard);
+    }
+
+    assert_eq!(before, OUTSTANDING.load(Ordering::SeqCst));
+
+    // Whichever of the last guard and the last Keep is dropped last frees everything, exactly once.
+    // Spawning threads under load occasionally makes std allocate a few things for good, so only
+    // what keep allocated is compared here.
+    let allocated = metrics::allocated_bytes();
+
+    for _ in 0..2000
+    {
+        let keep = Keep::new(String::from("Fleur"));
+        let guard = keep.read();
+
+        let dropper = thread::spawn(move || drop(guard));
+        drop(keep);
+        dropper.join().unwrap();
+    }
+
+    assert_eq!(allocated, metrics::allocated_bytes());
+}
@@ -0,0 +1,28 @@
+mod common;
+
+
+use common::THREAD_ALLOCS;
+use keep::*;
+
+
+#[test]
+fn immutable_reads_do_not_allocate()
+{
+    const GUARDS: usize = 64;
+
+    let keep = Keep::new_immutable(39);
+    let mut guards = Vec::with_capacity(GUARDS + 1);
+
+    let before = THREAD_ALLOCS.get();
+
+    // A regular Keep grows its domain for every guard alive at the same time.
+    for _ in 0..GUARDS
+    {
+        guards.push(keep.read());
+    }
+
+    guards.push(guards[0].clone());
+
+    assert_eq!(0, THREAD_ALLOCS.get() - before);
+    assert!(guards.iter().all(|guard| **guard == 39));
+}
@@ -0,0 +1,69 @@
+use keep::local::*;
+use std::cell::Cell;
+
+
+struct Canary<'a>(&'a Cell<usize>);
+impl Drop for Canary<'_>
+{
+    fn drop(&mut self)
+    {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+
+#[test]
+fn read_write()
+{
+    let a = LocalKeep::new(39);
+    let b = a.clone();
+    let guard = a.read();
+
+    b.write(3);
+    assert_eq!(3, *a.read());
+    assert_eq!(39, *guard);
+
+    assert_eq!(3, *a.swap(4));
+    assert_eq!(5, *b.update_and_fetch(|val| val + 1));
+    assert_eq!(5, *a.read());
+
+    assert!(LocalKeep::ptr_eq(&a, &b));
+    assert!(!LocalKeep::ptr_eq(&a, &LocalKeep::new(5)));
+}
+
+
+#[test]
+fn drops()
+{
+    let dropped = Cell::new(0);
+
+    let keep = LocalKeep::new(Canary(&dropped));
+    let guard = keep.read();
+
+    // The old value is kept alive by its guard.
+    keep.write(Canary(&dropped));
+    assert_eq!(0, dropped.get());
+
+    drop(guard);
+    assert_eq!(1, dropped.get());
+
+    drop(keep);
+    assert_eq!(2, dropped.get());
+}
+
+
+#[test]
+fn try_unwrap()
+{
+    let keep = LocalKeep::new(39);
+    let clone = keep.clone();
+
+    let keep = keep.try_unwrap().unwrap_err();
+    drop(clone);
+
+    let guard = keep.read();
+    let keep = keep.try_unwrap().unwrap_err();
+    drop(guard);
+
+    assert_eq!(Ok(39), keep.try_unwrap().map_err(|_| ()));
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "metrics")]
+
+
+use keep::{metrics::*, *};
+
+
+// The counter is global, so everything is checked in a single test.
+#[test]
+fn allocated_bytes_and_limit()
+{
+    let before = allocated_bytes();
+
+    {
+        let keep = Keep::new([0u8; 1024]);
+        let clone = keep.clone();
+        assert!(allocated_bytes() >= before + 1024);
+
+        let guard = keep.read();
+        clone.write([1; 1024]);
+        assert!(allocated_bytes() >= before + 2048);
+
+        drop(guard);
+        keep.write(Box::new([2; 1024]));
+        assert_eq!(2, clone.read()[0]);
+
+        // A raw `Heap` only counts once it is handed over to keep.
+        let handed_over = allocated_bytes();
+        let raw = unsafe { Heap::from_ptr(Box::into_raw(Box::new([3u8; 1024]))) };
+        assert_eq!(handed_over, allocated_bytes());
+
+        keep.write(raw);
+        assert!(allocated_bytes() >= handed_over + 1024);
+        assert_eq!(3, clone.read()[0]);
+    }
+
+    unsafe { quiesce() };
+    assert_eq!(before, allocated_bytes());
+
+    set_allocation_limit(Some(allocated_bytes()));
+    assert_eq!(Err(LimitExceeded), Keep::try_new(39).map(|_| ()));
+
+    set_allocation_limit(None);
+    assert_eq!(Some(39), Keep::try_new(39).ok().map(|keep| *keep.read()));
+}
@@ -0,0 +1,44 @@
+mod common;
+
+
+use common::OUTSTANDING;
+use keep::*;
+use std::sync::atomic::Ordering;
+
+
+#[test]
+fn dropping_frees_everything()
+{
+    let before = OUTSTANDING.load(Ordering::SeqCst);
+
+    {
+        let keep = Keep::new(String::from("Briar"));
+        let clone = keep.clone();
+        let guard = keep.read();
+
+        keep.write(String::from("Miku"));
+        clone.write(String::from("Gwen"));
+
+        assert_eq!("Briar", *guard);
+        assert_eq!("Gwen", *keep.swap(String::from("Kaisa")));
+        assert_eq!("Kaisa", *clone.read());
+    }
+
+    // Nothing is left for quiesce once the Keep is dropped.
+    assert_eq!(before, OUTSTANDING.load(Ordering::SeqCst));
+    assert_eq!(0, unsafe { quiesce() });
+
+    // Replaced values are freed while the Keep is alive, even with a guard to an old value around.
+    let keep = Keep::new(String::from("Briar"));
+    let guard = keep.read();
+    let alive = OUTSTANDING.load(Ordering::SeqCst);
+
+    for i in 0..10_000
+    {
+        keep.write(i.to_string());
+        drop(keep.read());
+    }
+
+    assert!(OUTSTANDING.load(Ordering::SeqCst) - alive < 200);
+    assert_eq!("Briar", *guard);
+}
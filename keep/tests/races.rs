@@ -0,0 +1,97 @@
+//! Readers and writers of the same `Keep` racing each other.
+
+
+use keep::*;
+use std::thread;
+
+
+const ALIVE: u64 = 0x5eed;
+
+
+/// Poisons itself when dropped, so a guard to a dropped value is noticed.
+struct Canary(u64);
+impl Drop for Canary
+{
+    fn drop(&mut self)
+    {
+        unsafe { std::ptr::write_volatile(&mut self.0, 0) };
+    }
+}
+
+
+#[test]
+fn read_races_write()
+{
+    let keep = Keep::new(Canary(ALIVE));
+
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let keep = keep.clone();
+            thread::spawn(move || {
+                for _ in 0..200_000
+                {
+                    if t == 0
+                    {
+                        keep.write(Canary(ALIVE));
+                    }
+                    else
+                    {
+                        // A value dropped between loading and registering the guard would be poisoned.
+                        assert_eq!(ALIVE, keep.read().0);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads
+    {
+        thread.join().unwrap();
+    }
+}
+
+
+#[test]
+fn swap_and_failed_exchange_race_read()
+{
+    let keep = Keep::new(Canary(ALIVE));
+
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let keep = keep.clone();
+            thread::spawn(move || {
+                // Readers hold on to the last value they read, so dropping it can free a value just swapped out.
+                let mut held = keep.read();
+
+                for _ in 0..200_000
+                {
+                    match t
+                    {
+                        0 => assert_eq!(ALIVE, keep.swap(Canary(ALIVE)).0),
+
+                        // held is never current again once thread 0 swapped, so the exchange fails
+                        // and returns a guard to the value thread 0 is about to swap out.
+                        1 =>
+                        {
+                            match keep.exchange(&held, Canary(ALIVE))
+                            {
+                                Ok(old) | Err(old) => assert_eq!(ALIVE, old.0),
+                            }
+                        }
+
+                        _ =>
+                        {
+                            held = keep.read();
+                            assert_eq!(ALIVE, held.0);
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads
+    {
+        thread.join().unwrap();
+    }
+}
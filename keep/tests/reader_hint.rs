@@ -0,0 +1,34 @@
+mod common;
+
+
+use common::THREAD_ALLOCS;
+use keep::*;
+use std::{sync::Barrier, thread};
+
+
+#[test]
+fn reader_hint()
+{
+    const READERS: usize = 4;
+
+    let keep = Keep::with_reader_hint(39, READERS);
+    let barrier = Barrier::new(READERS);
+
+    thread::scope(|s| {
+        for _ in 0..READERS
+        {
+            s.spawn(|| {
+                barrier.wait();
+
+                let before = THREAD_ALLOCS.get();
+                let guard = keep.read();
+                let allocs = THREAD_ALLOCS.get() - before;
+
+                // Keep all guards alive at the same time.
+                barrier.wait();
+                assert_eq!(0, allocs);
+                assert_eq!(39, *guard);
+            });
+        }
+    });
+}
@@ -0,0 +1,133 @@
+use keep::shared::*;
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+
+struct Canary<'a>(&'a AtomicUsize);
+impl Drop for Canary<'_>
+{
+    fn drop(&mut self)
+    {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+
+#[test]
+fn counts()
+{
+    let a = Shared::new(39);
+    assert_eq!(1, Shared::strong_count(&a));
+    assert_eq!(0, Shared::weak_count(&a));
+
+    let b = a.clone();
+    let w = Shared::downgrade(&a);
+    assert_eq!(2, Shared::strong_count(&a));
+    assert_eq!(1, Shared::weak_count(&b));
+    assert_eq!(2, w.strong_count());
+    assert_eq!(1, w.weak_count());
+
+    drop(a);
+    assert_eq!(1, Shared::strong_count(&b));
+
+    drop(b);
+    assert_eq!(0, w.strong_count());
+    assert_eq!(0, w.weak_count());
+}
+
+
+#[test]
+fn upgrade()
+{
+    let shared = Shared::new(String::from("Yuumi"));
+    let weak = Shared::downgrade(&shared);
+
+    assert_eq!("Yuumi", weak.upgrade().unwrap().as_str());
+
+    drop(shared);
+    assert!(weak.upgrade().is_none());
+}
+
+
+#[test]
+fn drops_once()
+{
+    let canary = AtomicUsize::new(0);
+
+    let shared = Shared::new(Canary(&canary));
+    let weak = Shared::downgrade(&shared);
+    let clone = shared.clone();
+
+    drop(shared);
+    assert_eq!(0, canary.load(Ordering::SeqCst));
+
+    drop(clone);
+    assert_eq!(1, canary.load(Ordering::SeqCst));
+
+    drop(weak);
+    assert_eq!(1, canary.load(Ordering::SeqCst));
+}
+
+
+#[test]
+fn get_mut()
+{
+    let mut shared = Shared::new(39);
+    *Shared::get_mut(&mut shared).unwrap() = 42;
+    assert_eq!(42, *shared);
+
+    let clone = shared.clone();
+    assert!(Shared::get_mut(&mut shared).is_none());
+    drop(clone);
+
+    let weak = Shared::downgrade(&shared);
+    assert!(Shared::get_mut(&mut shared).is_none());
+    drop(weak);
+
+    assert!(Shared::get_mut(&mut shared).is_some());
+}
+
+
+#[test]
+fn ptr_eq()
+{
+    let a = Shared::new(39);
+    let b = a.clone();
+    let c = Shared::new(39);
+
+    assert!(Shared::ptr_eq(&a, &b));
+    assert!(!Shared::ptr_eq(&a, &c));
+}
+
+
+#[test]
+fn many_threads()
+{
+    let shared = Shared::new(39usize);
+    let weak = Shared::downgrade(&shared);
+    let mut threads = vec![];
+
+    for _ in 0..10
+    {
+        let shared = shared.clone();
+        let weak = weak.clone();
+
+        threads.push(thread::spawn(move || {
+            for _ in 0..100
+            {
+                let upgraded = weak.upgrade().unwrap();
+                assert_eq!(*shared, *upgraded);
+            }
+        }));
+    }
+
+    for t in threads
+    {
+        t.join().unwrap();
+    }
+
+    assert_eq!(1, Shared::strong_count(&shared));
+    assert_eq!(1, Shared::weak_count(&shared));
+}
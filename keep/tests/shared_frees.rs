@@ -0,0 +1,36 @@
+mod common;
+
+
+use common::OUTSTANDING;
+use keep::shared::*;
+use std::sync::atomic::Ordering;
+
+
+#[test]
+fn shared_frees_everything()
+{
+    let before = OUTSTANDING.load(Ordering::SeqCst);
+
+    // The value is gone with the last strong handle, the control block with the last weak one.
+    let shared = Shared::new(String::from("Briar"));
+    let clone = shared.clone();
+    let weak = Shared::downgrade(&shared);
+
+    drop(shared);
+    drop(clone);
+    assert_eq!(before + 1, OUTSTANDING.load(Ordering::SeqCst));
+    assert!(weak.upgrade().is_none());
+
+    drop(weak);
+    assert_eq!(before, OUTSTANDING.load(Ordering::SeqCst));
+
+    for _ in 0..1000
+    {
+        let shared = Shared::new(39);
+        let weak = Shared::downgrade(&shared);
+        drop(weak.upgrade());
+        drop(shared);
+    }
+
+    assert_eq!(before, OUTSTANDING.load(Ordering::SeqCst));
+}
@@ -0,0 +1,30 @@
+mod common;
+
+
+use common::THREAD_ALLOCS;
+use keep::*;
+
+
+#[test]
+fn write_if_changed()
+{
+    let keep = Keep::new(39);
+    keep.write(3);
+
+    let before = THREAD_ALLOCS.get();
+    keep.write(3);
+    let per_write = THREAD_ALLOCS.get() - before;
+    assert!(per_write > 0);
+
+    // Writing an equal value allocates nothing.
+    let before = THREAD_ALLOCS.get();
+    for _ in 0..100
+    {
+        assert!(!keep.write_if_changed(3));
+    }
+    assert_eq!(before, THREAD_ALLOCS.get());
+
+    assert!(keep.write_if_changed(4));
+    assert_eq!(before + per_write, THREAD_ALLOCS.get());
+    assert_eq!(4, *keep.read());
+}
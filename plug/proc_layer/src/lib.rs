@@ -26,6 +26,8 @@ struct SimpleField
 enum Field
 {
     Layer(SimpleField),
+    // A layer field which falls back to a default layer according to the registry's `MissingPolicy`.
+    LayerOrDefault(SimpleField),
     Default(SimpleField),
     Data(DataField),
 }
@@ -82,6 +84,11 @@ impl Parse for Field
                     return Ok(Self::Layer(field));
                 }
 
+                if path == "layer_or_default"
+                {
+                    return Ok(Self::LayerOrDefault(field));
+                }
+
                 Err(syn::Error::new(path.span(), "unexpected attribute"))
             }
         }
@@ -95,7 +102,8 @@ impl ToTokens for Field
     {
         let stream = match self
         {
-            Field::Layer(SimpleField { ident, kind }) => quote! {#ident: Layer<#kind>},
+            Field::Layer(SimpleField { ident, kind })
+            | Field::LayerOrDefault(SimpleField { ident, kind }) => quote! {#ident: Layer<#kind>},
             Field::Default(SimpleField { ident, kind }) => quote! {#ident: #kind},
             Field::Data(DataField { ident, kind, .. }) => quote! {#ident: #kind},
         };
@@ -192,6 +200,23 @@ impl LayerStruct
             })
             .collect()
     }
+
+    pub fn layer_or_default_fields(&self) -> Vec<&SimpleField>
+    {
+        self.fields
+            .iter()
+            .filter_map(|e| {
+                if let Field::LayerOrDefault(data) = e
+                {
+                    Some(data)
+                }
+                else
+                {
+                    None
+                }
+            })
+            .collect()
+    }
 }
 
 
@@ -246,6 +271,10 @@ pub fn service(attr: TokenStream, input: TokenStream) -> TokenStream
     let layer_field_deps = layer_fields.iter().map(|l| l.kind.clone());
     let layer_field_names = layer_fields.iter().map(|l| l.ident.clone());
 
+    let layer_or_default_fields = layer_struct.layer_or_default_fields();
+    let layer_or_default_deps = layer_or_default_fields.iter().map(|l| l.kind.clone());
+    let layer_or_default_names = layer_or_default_fields.iter().map(|l| l.ident.clone());
+
     let default_fields = layer_struct.default_fields();
     let data_fields = layer_struct.data_fields();
 
@@ -263,19 +292,28 @@ pub fn service(attr: TokenStream, input: TokenStream) -> TokenStream
     #[rustfmt::skip]
     quote!
     {
-        static #context_identifier: StaticContext #generics = static_context!(#name, [#(#layer_field_deps),*]);
+        // Points out a missing dispatch impl, instead of only failing to build the context below.
+        const _: fn() = || {
+            fn assert_dispatch<T: ServiceDispatch #generics>() {}
+            assert_dispatch::<#name>();
+        };
+
+        static #context_identifier: StaticContext #generics =
+            static_context!(#name, [#(#layer_field_deps),*], [#(#layer_or_default_deps),*]);
         
         #visibility struct #name
         {
             #fields
         }
 
+        // Only `layer_or_default` fields are `Default`, so only they follow the registry's `MissingPolicy`.
         impl LayerConstruct #generics for #name
         {
             fn construct(reg: &Registry #generics) -> Self
             {
                 Self {
                     #(#layer_field_names: reg.get_unchecked(),)*
+                    #(#layer_or_default_names: reg.get_or_default(),)*
                     #(#default_fields,)*
                     #(#data_fields,)*
                 }
@@ -322,3 +360,79 @@ pub fn build_reg(attr: TokenStream) -> TokenStream
     }
     .into()
 }
+
+
+/// Collects all `#[service]` structs of an inline module and generates a `build()` function
+/// inside of it, which resolves them into a `Registry`.
+#[proc_macro_attribute]
+pub fn registry(_attr: TokenStream, input: TokenStream) -> TokenStream
+{
+    let mut module = syn::parse_macro_input!(input as syn::ItemMod);
+
+    let Some((_, items)) = &mut module.content
+    else
+    {
+        return syn::Error::new(module.span(), "registry modules must be inline")
+            .to_compile_error()
+            .into();
+    };
+
+    let mut contexts = vec![];
+    let mut generics = None;
+
+    for item in items.iter()
+    {
+        let syn::Item::Struct(item) = item
+        else
+        {
+            continue;
+        };
+
+        let Some(attr) = item.attrs.iter().find(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "service")
+        })
+        else
+        {
+            continue;
+        };
+
+        // Mirror the context identifier chosen by `service`.
+        let context_identifier = match &attr.meta
+        {
+            syn::Meta::List(list) =>
+            {
+                match list.parse_args::<syn::Ident>()
+                {
+                    Ok(ident) => ident,
+                    Err(err) => return err.to_compile_error().into(),
+                }
+            }
+
+            _ => format_ident!("{}", item.ident.to_string().to_uppercase()),
+        };
+
+        if generics.is_none() && !item.generics.params.is_empty()
+        {
+            let params = &item.generics.params;
+            generics = Some(quote! {<#params>});
+        }
+
+        contexts.push(context_identifier);
+    }
+
+    items.push(syn::Item::Verbatim(quote! {
+        /// Resolves all services of this module into a registry.
+        pub fn build() -> Registry #generics
+        {
+            Resolver::new()
+            #(.add_ctx(&#contexts))*
+            .build_reg()
+            .expect("Failed to build registry")
+        }
+    }));
+
+    module.into_token_stream().into()
+}
@@ -1,22 +1,226 @@
+use std::any::{Any, TypeId};
+
+
 pub enum NoDispatch {}
 
 
+/// Whether a layer acted on an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome<Res>
+{
+    /// The layer handled the event and produced a response.
+    Handled(Res),
+
+    /// The layer chose not to act on the event.
+    Skipped,
+}
+
+
+impl<Res> Outcome<Res>
+{
+    #[inline]
+    pub fn is_handled(&self) -> bool
+    {
+        matches!(self, Self::Handled(_))
+    }
+
+    #[inline]
+    pub fn is_skipped(&self) -> bool
+    {
+        matches!(self, Self::Skipped)
+    }
+
+    /// Returns the response, if the event was handled.
+    #[inline]
+    pub fn handled(self) -> Option<Res>
+    {
+        match self
+        {
+            Self::Handled(res) => Some(res),
+            Self::Skipped => None,
+        }
+    }
+}
+
+
+/// Counts how the layers of a registry reacted to a dispatched event.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DispatchReport
+{
+    pub handled: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+
+impl<'a, Res: 'a, Err: 'a> FromIterator<&'a Result<Outcome<Res>, Err>> for DispatchReport
+{
+    fn from_iter<I: IntoIterator<Item = &'a Result<Outcome<Res>, Err>>>(iter: I) -> Self
+    {
+        let mut report = Self::default();
+
+        for result in iter
+        {
+            match result
+            {
+                Ok(Outcome::Handled(_)) => report.handled += 1,
+                Ok(Outcome::Skipped) => report.skipped += 1,
+                Err(_) => report.failed += 1,
+            }
+        }
+
+        report
+    }
+}
+
+
+/// Why a layer failed to handle a dispatched event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DispatchError<Err>
+{
+    /// The layer returned an error.
+    Layer(Err),
+
+    /// The layer with this `TypeId` did not return in time.
+    TimedOut(TypeId),
+}
+
+
 pub trait LayerDispatch<E>
 {
     type Error;
     type Response;
-    fn layer_dispatch(&self, event: &E) -> Result<Self::Response, Self::Error>;
+    fn layer_dispatch(&self, event: &E) -> Result<Outcome<Self::Response>, Self::Error>;
+
+    /// Like `layer_dispatch`, but hands the response to `sink` by reference instead of returning it.
+    ///
+    /// Used by `Registry::dispatch_writing`. Layers which keep their responses around anyway,
+    /// like in a buffer, can override this to not move a response out for every event.
+    fn layer_dispatch_into(
+        &self,
+        event: &E,
+        sink: &mut dyn FnMut(&Self::Response),
+    ) -> Result<Outcome<()>, Self::Error>
+    {
+        Ok(match self.layer_dispatch(event)?
+        {
+            Outcome::Handled(res) =>
+            {
+                sink(&res);
+                Outcome::Handled(())
+            }
+            Outcome::Skipped => Outcome::Skipped,
+        })
+    }
+
+    /// Called by `Registry::shutdown` right before the layer is removed,
+    /// and by `Registry::replace_or_insert` once the layer was replaced.
+    fn on_remove(&self) {}
 }
 
 pub trait SimpleDispatch<E>
 {
-    fn simple_dispatch(&self, _event: &E) {}
+    /// Reacts to `event`, layers which do not act on an event should return `Outcome::Skipped`.
+    fn simple_dispatch(&self, _event: &E) -> Outcome<()>
+    {
+        Outcome::Skipped
+    }
+
+    /// Called by `Registry::shutdown` right before the layer is removed,
+    /// and by `Registry::replace_or_insert` once the layer was replaced.
+    fn on_remove(&self) {}
+}
+
+
+/// An event of one of two types, lets a registry dispatch more than one event type.
+///
+/// Every `RouteEvents` layer implementing both `SimpleDispatch<A>` and `SimpleDispatch<B>` dispatches
+/// `Either<A, B>`, each event is routed to the impl for its variant. Layers only interested in one of the
+/// types can leave the other impl empty, it skips all events by default. Nest `Either`s for more than two types.
+///
+/// Only the `on_remove` hook of the `SimpleDispatch<A>` impl is called, so it is not called twice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<A, B>
+{
+    Left(A),
+    Right(B),
 }
 
 
+/// Opts a layer into dispatching `Either` events by routing them to its impls for the variants.
+///
+/// Without opting in, every type would be a candidate for dispatching `Either`s, so the event type
+/// of a `Registry::new()` could no longer be inferred from the layers inserted into it.
+pub trait RouteEvents {}
+
+
+impl<A, B, T> SimpleDispatch<Either<A, B>> for T
+where
+    T: RouteEvents + SimpleDispatch<A> + SimpleDispatch<B>,
+{
+    fn simple_dispatch(&self, event: &Either<A, B>) -> Outcome<()>
+    {
+        match event
+        {
+            Either::Left(event) => SimpleDispatch::<A>::simple_dispatch(self, event),
+            Either::Right(event) => SimpleDispatch::<B>::simple_dispatch(self, event),
+        }
+    }
+
+    fn on_remove(&self)
+    {
+        SimpleDispatch::<A>::on_remove(self)
+    }
+}
+
+
+/// Implemented by every layer dispatching events of type `E`, used by the `service` macro
+/// to point out services which do not implement `SimpleDispatch` or `LayerDispatch`.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "the service `{Self}` must implement `SimpleDispatch` or `LayerDispatch`",
+    label = "this service does not dispatch `{E}`",
+    note = "implement `SimpleDispatch<{E}>`, or `LayerDispatch<{E}>` to respond with values or errors"
+)]
+pub trait ServiceDispatch<E = NoDispatch, Err = (), Res = ()> {}
+
+
+impl<E, Err, Res, T> ServiceDispatch<E, Err, Res> for T where
+    T: LayerDispatch<E, Error = Err, Response = Res>
+{
+}
+
+
+/// Returns `self` as `&dyn Any`, implemented for every `'static` type.
+pub trait AsAny
+{
+    fn as_any(&self) -> &dyn Any;
+}
+
+
+impl<T: Any> AsAny for T
+{
+    #[inline]
+    fn as_any(&self) -> &dyn Any
+    {
+        self
+    }
+}
+
+
+/// A layer which can be downcast back to its concrete type, implemented for every `'static` layer.
+pub trait AnyLayer<E>: LayerDispatch<E> + AsAny {}
+
+
+impl<E, T: LayerDispatch<E> + AsAny> AnyLayer<E> for T {}
+
+
 impl<T> SimpleDispatch<NoDispatch> for T
 {
-    fn simple_dispatch(&self, _event: &NoDispatch) {}
+    fn simple_dispatch(&self, _event: &NoDispatch) -> Outcome<()>
+    {
+        Outcome::Skipped
+    }
 }
 
 
@@ -25,9 +229,13 @@ impl<E, T: SimpleDispatch<E>> LayerDispatch<E> for T
     type Error = ();
     type Response = ();
 
-    fn layer_dispatch(&self, event: &E) -> Result<Self::Response, Self::Error>
+    fn layer_dispatch(&self, event: &E) -> Result<Outcome<Self::Response>, Self::Error>
+    {
+        Ok(self.simple_dispatch(event))
+    }
+
+    fn on_remove(&self)
     {
-        self.simple_dispatch(event);
-        Ok(())
+        SimpleDispatch::on_remove(self)
     }
 }
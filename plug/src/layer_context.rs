@@ -1,8 +1,8 @@
 use crate::{
     dispatch::{LayerDispatch, NoDispatch},
-    registry::Registry,
+    registry::{BoxedLayer, Registry},
 };
-use keep::{Guard, Heap, Keep};
+use keep::{Guard, Keep};
 use std::any::TypeId;
 
 
@@ -21,6 +21,7 @@ macro_rules! dep_vec
 macro_rules! static_context {
     ($layer:ident) => {::std::sync::LazyLock::new(|| $crate::layer_context::LayerContext::new::<$layer>($crate::dep_vec![]))};
     ($layer:ident, [$($dep:ident),*] ) => {::std::sync::LazyLock::new(|| $crate::layer_context::LayerContext::new::<$layer>($crate::dep_vec![$($dep),*]))};
+    ($layer:ident, [$($dep:ident),*], [$($optional:ident),*] ) => {::std::sync::LazyLock::new(|| $crate::layer_context::LayerContext::new::<$layer>($crate::dep_vec![$($dep),*]).with_optional_deps($crate::dep_vec![$($optional),*]))};
 }
 
 
@@ -34,9 +35,7 @@ where
 
 pub trait LayerConstructor<E, Err, Res>
 {
-    fn constructor()
-    -> impl Fn(&Registry<E, Err, Res>) -> Box<dyn LayerDispatch<E, Error = Err, Response = Res>>
-    + 'static;
+    fn constructor() -> impl Fn(&Registry<E, Err, Res>) -> BoxedLayer<E, Err, Res> + 'static;
 }
 
 
@@ -47,8 +46,7 @@ where
     Res: 'static,
     T: LayerConstruct<E, Err, Res> + LayerDispatch<E, Error = Err, Response = Res> + 'static,
 {
-    fn constructor()
-    -> impl Fn(&Registry<E, Err, Res>) -> Box<dyn LayerDispatch<E, Error = Err, Response = Res>>
+    fn constructor() -> impl Fn(&Registry<E, Err, Res>) -> BoxedLayer<E, Err, Res>
     {
         |registry| Box::new(Self::construct(registry))
     }
@@ -60,13 +58,8 @@ pub struct LayerContext<E, Err, Res>
 {
     type_id: TypeId,
     deps: Vec<TypeId>,
-    constructor: Guard<
-        Box<
-            dyn Fn(
-                &Registry<E, Err, Res>,
-            ) -> Box<dyn LayerDispatch<E, Error = Err, Response = Res>>,
-        >,
-    >,
+    optional_deps: Vec<TypeId>,
+    constructor: Guard<Box<dyn Fn(&Registry<E, Err, Res>) -> BoxedLayer<E, Err, Res>>>,
 }
 
 
@@ -82,23 +75,28 @@ impl<E, Err, Res> LayerContext<E, Err, Res>
     {
         #[allow(clippy::type_complexity)]
         let constructor: Box<
-            dyn Fn(
-                    &Registry<E, Err, Res>,
-                )
-                    -> Box<dyn LayerDispatch<E, Error = Err, Response = Res> + 'static>
-                + 'static,
+            dyn Fn(&Registry<E, Err, Res>) -> BoxedLayer<E, Err, Res> + 'static,
         > = Box::new(C::constructor());
 
-        let constructor = unsafe { Heap::from_ptr(Box::into_raw(Box::new(constructor))) };
-        let constructor: Keep<Box<_>> = Keep::new(constructor);
+        let constructor: Keep<Box<_>> = Keep::new(Box::new(constructor));
 
         Self {
             type_id: TypeId::of::<C>(),
             deps,
+            optional_deps: vec![],
             constructor: constructor.read(),
         }
     }
 
+    /// Adds dependencies which the layer can be constructed without, see `MissingPolicy`.
+    ///
+    /// A `Resolver` still constructs the layer after those of them it resolves as well.
+    pub fn with_optional_deps(mut self, optional_deps: Vec<TypeId>) -> Self
+    {
+        self.optional_deps = optional_deps;
+        self
+    }
+
     pub(crate) fn insert_into_reg(&self, reg: &Registry<E, Err, Res>)
     {
         unsafe {
@@ -110,6 +108,11 @@ impl<E, Err, Res> LayerContext<E, Err, Res>
         self.deps.clone()
     }
 
+    pub(crate) fn optional_deps(&self) -> &[TypeId]
+    {
+        &self.optional_deps
+    }
+
     pub(crate) fn id(&self) -> TypeId
     {
         self.type_id
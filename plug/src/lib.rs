@@ -1,7 +1,9 @@
 pub mod dispatch;
 pub mod layer_context;
+pub mod multi_registry;
 pub mod registry;
 pub mod resolver;
+pub mod scoped_registry;
 
 
 #[cfg(feature = "macro")]
@@ -9,14 +11,36 @@ pub use proc_layer;
 
 pub mod prelude
 {
-    pub use crate::dispatch::{LayerDispatch, NoDispatch, SimpleDispatch};
+    pub use crate::dispatch::{
+        AnyLayer,
+        AsAny,
+        DispatchError,
+        DispatchReport,
+        Either,
+        LayerDispatch,
+        NoDispatch,
+        Outcome,
+        RouteEvents,
+        ServiceDispatch,
+        SimpleDispatch,
+    };
     pub use crate::layer_context::{LayerConstruct, StaticContext};
-    pub use crate::registry::{Layer, Registry};
-    pub use crate::resolver::Resolver;
+    pub use crate::multi_registry::MultiRegistry;
+    pub use crate::registry::{
+        BoxedLayer,
+        Layer,
+        MissingPolicy,
+        OrderError,
+        Registry,
+        RegistryBuilder,
+        RegistryHandle,
+    };
+    pub use crate::resolver::{ResolveError, Resolver};
+    pub use crate::scoped_registry::{ScopedLayer, ScopedRegistry};
     pub use crate::static_context;
 
     #[cfg(feature = "macro")]
-    pub use proc_layer::{build_reg, service};
+    pub use proc_layer::{build_reg, registry, service};
 }
 
 
@@ -24,7 +48,13 @@ pub mod prelude
 mod tests
 {
     use super::prelude::*;
-    use std::{fmt::Display, thread};
+    use std::{
+        any::TypeId,
+        cell::{Cell, RefCell},
+        fmt::Display,
+        rc::Rc,
+        thread,
+    };
 
     struct Cat(&'static str);
     impl Cat
@@ -45,16 +75,18 @@ mod tests
 
     impl SimpleDispatch<String> for Cat
     {
-        fn simple_dispatch(&self, event: &String)
+        fn simple_dispatch(&self, event: &String) -> Outcome<()>
         {
             println!("{}: mission {}!!", self.0, event);
+
+            Outcome::Handled(())
         }
     }
 
     #[test]
     fn look_and_feel()
     {
-        print!("\n");
+        println!();
         let reg = Registry::new();
         reg.insert(Cat("Fleur"));
         reg.get_unchecked::<Cat>().meow();
@@ -64,7 +96,7 @@ mod tests
     #[test]
     fn multiple_threads()
     {
-        print!("\n");
+        println!();
 
         let reg = Registry::<String>::new();
         let reg_clone = reg.clone();
@@ -89,6 +121,598 @@ mod tests
         t2.join().unwrap();
     }
 
+    #[test]
+    fn dispatch_map()
+    {
+        struct Dog;
+        impl SimpleDispatch<String> for Dog {}
+
+        let reg = Registry::<String>::new();
+        reg.insert(Cat("Fleur"));
+        reg.insert(Dog);
+
+        let mut type_ids: Vec<_> = reg
+            .dispatch_map(&"Fetch".to_string())
+            .into_iter()
+            .map(|(type_id, res)| {
+                assert!(res.is_ok());
+                type_id
+            })
+            .collect();
+
+        let mut expected = vec![TypeId::of::<Cat>(), TypeId::of::<Dog>()];
+
+        type_ids.sort();
+        expected.sort();
+        assert_eq!(expected, type_ids);
+    }
+
+    #[test]
+    fn set_order()
+    {
+        struct Dog;
+        struct Fox;
+        impl SimpleDispatch<String> for Dog {}
+        impl SimpleDispatch<String> for Fox {}
+
+        let reg = Registry::<String>::new();
+        reg.insert(Cat("Fleur"));
+        reg.insert(Dog);
+
+        let dispatched = |reg: &Registry<String>| {
+            reg.dispatch_map(&"Fetch".to_string())
+                .into_iter()
+                .map(|(type_id, _)| type_id)
+                .collect::<Vec<_>>()
+        };
+
+        let (cat, dog, fox) = (
+            TypeId::of::<Cat>(),
+            TypeId::of::<Dog>(),
+            TypeId::of::<Fox>(),
+        );
+        assert_eq!(vec![cat, dog], dispatched(&reg));
+
+        reg.set_order(vec![dog, cat]).unwrap();
+        assert_eq!(vec![dog, cat], reg.order());
+        assert_eq!(vec![dog, cat], dispatched(&reg));
+
+        let error = reg.set_order(vec![fox, dog, dog]).unwrap_err();
+        assert_eq!(vec![fox], error.unknown);
+        assert_eq!(vec![cat], error.missing);
+        assert_eq!(vec![dog], error.duplicated);
+
+        // Invalid orders leave the order untouched.
+        assert_eq!(vec![dog, cat], dispatched(&reg));
+    }
+
+    #[test]
+    fn missing_policy()
+    {
+        #[derive(Default)]
+        struct Config(usize);
+        struct Plugin
+        {
+            config: Layer<Config>,
+        }
+
+        impl LayerConstruct for Plugin
+        {
+            fn construct(registry: &Registry) -> Self
+            {
+                Self {
+                    config: registry.get_or_default(),
+                }
+            }
+        }
+
+        let reg = Registry::new();
+
+        // Panics by default, like `get_unchecked`.
+        let constructed =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| Plugin::construct(&reg)));
+        assert!(constructed.is_err());
+
+        reg.set_missing_policy(MissingPolicy::ReturnDefault);
+        let plugin = Plugin::construct(&reg);
+        assert_eq!(0, plugin.config.0);
+
+        // The fallback is not inserted, and present layers are still preferred.
+        assert!(reg.get::<Config>().is_none());
+        reg.insert(Config(39));
+        assert_eq!(39, Plugin::construct(&reg).config.0);
+    }
+
+    #[test]
+    fn dispatch_presized()
+    {
+        struct A;
+        struct B;
+        struct C;
+        impl SimpleDispatch<usize> for A {}
+        impl SimpleDispatch<usize> for B {}
+        impl SimpleDispatch<usize> for C {}
+
+        let reg = Registry::<usize>::new();
+        reg.insert(A);
+        reg.insert(B);
+        reg.insert(C);
+
+        // The results are collected into a vector sized for all layers up front.
+        let results = reg.dispatch(&39);
+        assert_eq!(3, results.len());
+        assert_eq!(reg.len(), results.capacity());
+
+        let results = reg.dispatch_map(&39);
+        assert_eq!(reg.len(), results.capacity());
+    }
+
+    #[test]
+    fn replace_or_insert()
+    {
+        // Records the version of the layer that handled the last event and of the last removed one.
+        struct Plugin(usize, Rc<Cell<usize>>, Rc<Cell<usize>>);
+        impl SimpleDispatch<usize> for Plugin
+        {
+            fn simple_dispatch(&self, _: &usize) -> Outcome<()>
+            {
+                self.1.set(self.0);
+                Outcome::Handled(())
+            }
+
+            fn on_remove(&self)
+            {
+                self.2.set(self.0);
+            }
+        }
+
+        let handled = Rc::new(Cell::new(0));
+        let removed = Rc::new(Cell::new(0));
+        let reg = Registry::<usize>::new();
+        assert!(
+            reg.replace_or_insert(Plugin(1, handled.clone(), removed.clone()))
+                .is_none()
+        );
+        assert_eq!(0, removed.get());
+
+        let old = reg
+            .replace_or_insert(Plugin(2, handled.clone(), removed.clone()))
+            .unwrap();
+        assert_eq!(1, old.0);
+        assert_eq!(1, removed.get());
+        assert_eq!(1, reg.len());
+
+        reg.dispatch(&39);
+        assert_eq!(2, handled.get());
+
+        // The old layer is still usable, even though the registry no longer dispatches to it.
+        SimpleDispatch::<usize>::simple_dispatch(&**old, &39);
+        assert_eq!(1, handled.get());
+        drop(old);
+        assert_eq!(2, reg.get_unchecked::<Plugin>().0);
+    }
+
+    #[test]
+    fn either_event()
+    {
+        struct Logger(RefCell<Vec<String>>);
+        struct Counter(Cell<usize>);
+
+        impl SimpleDispatch<String> for Logger
+        {
+            fn simple_dispatch(&self, event: &String) -> Outcome<()>
+            {
+                self.0.borrow_mut().push(event.clone());
+                Outcome::Handled(())
+            }
+        }
+        impl SimpleDispatch<usize> for Logger {}
+        impl RouteEvents for Logger {}
+
+        impl SimpleDispatch<String> for Counter {}
+        impl RouteEvents for Counter {}
+        impl SimpleDispatch<usize> for Counter
+        {
+            fn simple_dispatch(&self, event: &usize) -> Outcome<()>
+            {
+                self.0.set(self.0.get() + event);
+                Outcome::Handled(())
+            }
+        }
+
+        let reg = Registry::<Either<String, usize>>::new();
+        reg.insert(Logger(RefCell::new(vec![])));
+        reg.insert(Counter(Cell::new(0)));
+
+        let report = reg.dispatch_report(&Either::Left("Briar".to_string()));
+        assert_eq!((1, 1), (report.handled, report.skipped));
+
+        let report = reg.dispatch_report(&Either::Right(39));
+        assert_eq!((1, 1), (report.handled, report.skipped));
+
+        assert_eq!(vec!["Briar"], *reg.get_unchecked::<Logger>().0.borrow());
+        assert_eq!(39, reg.get_unchecked::<Counter>().0.get());
+    }
+
+    #[test]
+    fn registry_handle()
+    {
+        fn fire(handle: &dyn RegistryHandle<usize>) -> usize
+        {
+            handle.dispatch(&39).len()
+        }
+
+        struct Tally(Cell<usize>);
+
+        impl SimpleDispatch<usize> for Tally
+        {
+            fn simple_dispatch(&self, event: &usize) -> Outcome<()>
+            {
+                self.0.set(self.0.get() + event);
+
+                Outcome::Handled(())
+            }
+        }
+
+        let reg = Registry::<usize>::new();
+        reg.insert(Tally(Cell::new(0)));
+
+        assert_eq!(1, fire(&reg));
+        assert_eq!(1, RegistryHandle::len(&reg));
+        assert!((&reg as &dyn RegistryHandle<usize>).contains_type(TypeId::of::<Tally>()));
+        assert!(!(&reg as &dyn RegistryHandle<usize>).contains_type(TypeId::of::<Cat>()));
+        assert_eq!(39, reg.get_unchecked::<Tally>().0.get());
+    }
+
+    #[test]
+    fn dispatch_report()
+    {
+        struct Even;
+
+        impl SimpleDispatch<usize> for Even
+        {
+            fn simple_dispatch(&self, event: &usize) -> Outcome<()>
+            {
+                match event % 2
+                {
+                    0 => Outcome::Handled(()),
+                    _ => Outcome::Skipped,
+                }
+            }
+        }
+
+        struct Idle;
+        impl SimpleDispatch<usize> for Idle {}
+
+        let reg = Registry::<usize>::new();
+        reg.insert(Even);
+        reg.insert(Idle);
+
+        let report = reg.dispatch_report(&2);
+        assert_eq!((1, 1, 0), (report.handled, report.skipped, report.failed));
+
+        let report = reg.dispatch_report(&3);
+        assert_eq!((0, 2, 0), (report.handled, report.skipped, report.failed));
+    }
+
+    #[test]
+    fn reentrant_dispatch()
+    {
+        // Dispatches every event it receives to its own registry again.
+        struct Echo
+        {
+            reg: RefCell<Option<Registry<usize>>>,
+            nested: Cell<Option<usize>>,
+        }
+
+        impl SimpleDispatch<usize> for Echo
+        {
+            fn simple_dispatch(&self, event: &usize) -> Outcome<()>
+            {
+                if let Some(reg) = self.reg.borrow().as_ref()
+                {
+                    self.nested.set(Some(reg.dispatch(event).len()));
+                }
+
+                Outcome::Handled(())
+            }
+        }
+
+        let reg = Registry::<usize>::new();
+        reg.insert(Echo {
+            reg: RefCell::new(Some(reg.clone())),
+            nested: Cell::new(None),
+        });
+
+        assert_eq!(1, reg.dispatch(&39).len());
+        assert_eq!(Some(0), reg.get_unchecked::<Echo>().nested.get());
+
+        struct Idle;
+        impl SimpleDispatch<usize> for Idle {}
+
+        // Other registries can still be dispatched to from within a layer.
+        let other = Registry::new();
+        other.insert(Idle);
+        reg.get_unchecked::<Echo>().reg.replace(Some(other));
+
+        assert_eq!(1, reg.dispatch_map(&3).len());
+        assert_eq!(Some(1), reg.get_unchecked::<Echo>().nested.get());
+    }
+
+    #[test]
+    fn from_layers()
+    {
+        struct Dog;
+        impl SimpleDispatch<String> for Dog {}
+
+        let reg = unsafe {
+            Registry::<String>::from_layers(vec![
+                (TypeId::of::<Cat>(), Box::new(Cat("Fleur"))),
+                (TypeId::of::<Dog>(), Box::new(Dog)),
+            ])
+        };
+
+        assert_eq!(vec![TypeId::of::<Cat>(), TypeId::of::<Dog>()], reg.order());
+        reg.get_unchecked::<Cat>().meow();
+
+        let report = reg.dispatch_report(&"Fetch".to_string());
+        assert_eq!((1, 1), (report.handled, report.skipped));
+    }
+
+    #[test]
+    fn dispatch_writing()
+    {
+        // Keeps its response around, so it can be handed out by reference.
+        struct Echo(RefCell<String>);
+
+        impl LayerDispatch<usize> for Echo
+        {
+            type Error = usize;
+            type Response = String;
+
+            fn layer_dispatch(&self, event: &usize) -> Result<Outcome<String>, usize>
+            {
+                self.layer_dispatch_into(event, &mut |_| ())?;
+                Ok(Outcome::Handled(self.0.borrow().clone()))
+            }
+
+            fn layer_dispatch_into(
+                &self,
+                event: &usize,
+                sink: &mut dyn FnMut(&String),
+            ) -> Result<Outcome<()>, usize>
+            {
+                let mut res = self.0.borrow_mut();
+                res.clear();
+                res.push_str(&event.to_string());
+
+                sink(&res);
+                Ok(Outcome::Handled(()))
+            }
+        }
+
+        // Uses the provided `layer_dispatch_into`.
+        struct Even;
+
+        impl LayerDispatch<usize> for Even
+        {
+            type Error = usize;
+            type Response = String;
+
+            fn layer_dispatch(&self, event: &usize) -> Result<Outcome<String>, usize>
+            {
+                match event % 2
+                {
+                    0 => Ok(Outcome::Handled(String::from("even"))),
+                    _ => Err(*event),
+                }
+            }
+        }
+
+        let reg = Registry::<usize, usize, String>::new();
+        reg.insert(Echo(RefCell::new(String::new())));
+        reg.insert(Even);
+
+        let mut written = vec![];
+        let report =
+            reg.dispatch_writing(&2, &mut |type_id, res| written.push((type_id, res.clone())));
+
+        assert_eq!((2, 0, 0), (report.handled, report.skipped, report.failed));
+        assert_eq!(
+            vec![
+                (TypeId::of::<Echo>(), String::from("2")),
+                (TypeId::of::<Even>(), String::from("even")),
+            ],
+            written
+        );
+
+        let report = reg.dispatch_writing(&39, &mut |type_id, _| {
+            assert_eq!(TypeId::of::<Echo>(), type_id)
+        });
+        assert_eq!((1, 0, 1), (report.handled, report.skipped, report.failed));
+    }
+
+    #[test]
+    fn checked_get()
+    {
+        struct Dog;
+        impl SimpleDispatch<String> for Dog {}
+
+        let reg = Registry::<String>::new();
+        reg.insert(Cat("Fleur"));
+
+        // Deliberately store a cat under the TypeId of a dog.
+        unsafe { reg.insert_by(Box::new(Cat("Briar")), TypeId::of::<Dog>()) };
+
+        assert_eq!("Fleur", reg.get::<Cat>().unwrap().0);
+        assert!(reg.contains_type(TypeId::of::<Dog>()));
+        assert!(reg.get::<Dog>().is_none());
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn dispatch_with_timeout()
+    {
+        use std::time::Duration;
+
+        struct Fast;
+        struct Slow;
+        struct Failing;
+
+        impl SimpleDispatch<usize> for Fast
+        {
+            fn simple_dispatch(&self, _event: &usize) -> Outcome<()>
+            {
+                Outcome::Handled(())
+            }
+        }
+
+        impl SimpleDispatch<usize> for Slow
+        {
+            fn simple_dispatch(&self, _event: &usize) -> Outcome<()>
+            {
+                thread::sleep(Duration::from_secs(1));
+                Outcome::Handled(())
+            }
+        }
+
+        impl LayerDispatch<usize> for Failing
+        {
+            type Error = ();
+            type Response = ();
+
+            fn layer_dispatch(&self, _event: &usize) -> Result<Outcome<()>, ()>
+            {
+                Err(())
+            }
+        }
+
+        let reg = Registry::<usize>::new();
+        reg.insert(Fast);
+        reg.insert(Slow);
+        unsafe { reg.insert_by(Box::new(Failing), TypeId::of::<Failing>()) };
+
+        let results = reg.dispatch_with_timeout(&39, Duration::from_millis(50));
+
+        assert_eq!(
+            vec![
+                Ok(Outcome::Handled(())),
+                Err(DispatchError::TimedOut(TypeId::of::<Slow>())),
+                Err(DispatchError::Layer(())),
+            ],
+            results
+        );
+    }
+
+    #[test]
+    fn shutdown()
+    {
+        use std::sync::{Arc, Mutex};
+
+        type Log = Arc<Mutex<Vec<&'static str>>>;
+
+        struct Db(Log);
+        struct Users(Layer<Db>, Log);
+        struct Logger(Log, Registry<String>);
+
+        impl SimpleDispatch<String> for Db
+        {
+            fn on_remove(&self)
+            {
+                self.0.lock().unwrap().push("db");
+            }
+        }
+
+        impl SimpleDispatch<String> for Users
+        {
+            fn on_remove(&self)
+            {
+                // The db is still around while its dependents shut down.
+                assert!(Arc::ptr_eq(&self.0.0, &self.1));
+                self.1.lock().unwrap().push("users");
+            }
+        }
+
+        impl SimpleDispatch<String> for Logger
+        {
+            fn on_remove(&self)
+            {
+                // Layers are removed only after their hook ran.
+                assert!(self.1.get::<Logger>().is_some());
+                self.0.lock().unwrap().push("logger");
+            }
+        }
+
+        let log = Log::default();
+        let reg = Registry::<String>::new();
+
+        reg.insert(Logger(log.clone(), reg.clone()));
+        reg.insert(Db(log.clone()));
+        reg.insert(Users(reg.get_unchecked::<Db>(), log.clone()));
+
+        reg.shutdown();
+
+        assert_eq!(vec!["users", "db", "logger"], *log.lock().unwrap());
+        assert!(reg.is_empty());
+        assert!(reg.order().is_empty());
+    }
+
+    #[test]
+    fn multi_registry()
+    {
+        struct Tally(Cell<usize>);
+
+        impl SimpleDispatch<usize> for Tally
+        {
+            fn simple_dispatch(&self, event: &usize) -> Outcome<()>
+            {
+                self.0.set(self.0.get() + event);
+                Outcome::Handled(())
+            }
+        }
+
+        let numbers = Registry::<usize>::new();
+        numbers.insert(Tally(Cell::new(0)));
+
+        let words = Registry::<String>::new();
+        words.insert(Cat("Fleur"));
+
+        let multi = MultiRegistry::new();
+        multi.register(numbers.clone());
+        multi.register(words);
+
+        assert_eq!(1, multi.dispatch(&39usize).unwrap().len());
+        assert_eq!(1, multi.dispatch(&"Nap".to_string()).unwrap().len());
+        assert!(multi.dispatch(&'c').is_none());
+
+        assert_eq!(39, numbers.get_unchecked::<Tally>().0.get());
+        assert!(multi.registry::<String>().unwrap().get::<Cat>().is_some());
+    }
+
+    #[test]
+    fn builder()
+    {
+        struct Numbered<const N: usize>;
+        impl<const N: usize> SimpleDispatch<usize> for Numbered<N> {}
+
+        let reg = Registry::builder::<usize, (), ()>().capacity(40).build();
+        let capacity = reg.capacity();
+        assert!(capacity >= 40);
+
+        macro_rules! insert {
+            ($($n:literal)*) => { $(reg.insert(Numbered::<$n>);)* };
+        }
+
+        insert!(
+            0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19
+            20 21 22 23 24 25 26 27 28 29 30 31 32 33 34 35 36 37 38 39
+        );
+
+        assert_eq!(40, reg.len());
+        assert_eq!(capacity, reg.capacity());
+        assert_eq!(40, reg.dispatch_report(&39).skipped);
+    }
+
     #[test]
     fn resolver()
     {
@@ -129,6 +753,297 @@ mod tests
         assert_eq!("Test", reg.get_unchecked::<B>().data());
     }
 
+    #[test]
+    fn resolver_build()
+    {
+        static A_CTX: StaticContext = static_context!(A);
+        static B_CTX: StaticContext = static_context!(B, [A]);
+        static C_CTX: StaticContext = static_context!(C, [D]);
+
+        struct A;
+        struct B
+        {
+            _a: Layer<A>,
+        }
+        struct C;
+        struct D;
+
+        impl LayerConstruct for A
+        {
+            fn construct(_registry: &Registry) -> Self
+            {
+                Self
+            }
+        }
+
+        impl LayerConstruct for B
+        {
+            fn construct(registry: &Registry) -> Self
+            {
+                Self {
+                    _a: registry.get_unchecked(),
+                }
+            }
+        }
+
+        impl LayerConstruct for C
+        {
+            fn construct(_registry: &Registry) -> Self
+            {
+                Self
+            }
+        }
+
+        let resolver = Resolver::new().add_ctx(&B_CTX).add_ctx(&A_CTX);
+        let first = resolver.build().unwrap();
+        let second = resolver.build().unwrap();
+
+        let expected = vec![TypeId::of::<A>(), TypeId::of::<B>()];
+        assert_eq!(expected, first.order());
+        assert_eq!(expected, second.order());
+
+        // D is never added, so C can not be resolved.
+        let resolver = resolver.add_ctx(&C_CTX);
+        assert_eq!(
+            Err(ResolveError {
+                unresolved: vec![TypeId::of::<C>()]
+            }),
+            resolver.build().map(|_| ())
+        );
+    }
+
+    #[cfg(feature = "test-hooks")]
+    #[test]
+    fn with_dummy_layers()
+    {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DISPATCHED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone)]
+        struct Dummy;
+
+        impl SimpleDispatch<usize> for Dummy
+        {
+            fn simple_dispatch(&self, _event: &usize) -> Outcome<()>
+            {
+                DISPATCHED.fetch_add(1, Ordering::SeqCst);
+                Outcome::Handled(())
+            }
+        }
+
+        let reg = Registry::with_dummy_layers(1000, Dummy);
+
+        assert_eq!(1000, reg.len());
+        assert!(reg.get::<Dummy>().is_none());
+
+        let results = reg.dispatch(&39);
+
+        assert_eq!(1000, results.len());
+        assert_eq!(1000, DISPATCHED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn warm()
+    {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static A_CTX: StaticContext = static_context!(A);
+        static B_CTX: StaticContext = static_context!(B, [A]);
+        static C_CTX: StaticContext = static_context!(C, [D]);
+        static CONSTRUCTED: AtomicUsize = AtomicUsize::new(0);
+
+        struct A;
+        struct B
+        {
+            _a: Layer<A>,
+        }
+        struct C;
+        struct D;
+
+        impl LayerConstruct for A
+        {
+            fn construct(_registry: &Registry) -> Self
+            {
+                CONSTRUCTED.fetch_add(1, Ordering::SeqCst);
+                Self
+            }
+        }
+
+        impl LayerConstruct for B
+        {
+            fn construct(registry: &Registry) -> Self
+            {
+                CONSTRUCTED.fetch_add(1, Ordering::SeqCst);
+                Self {
+                    _a: registry.get_unchecked(),
+                }
+            }
+        }
+
+        impl LayerConstruct for C
+        {
+            fn construct(_registry: &Registry) -> Self
+            {
+                Self
+            }
+        }
+
+        let reg = Registry::new();
+
+        // B is listed before its dependency A.
+        assert_eq!(Ok(()), reg.warm(&[&B_CTX, &A_CTX]));
+        assert!(reg.contains_type(TypeId::of::<A>()));
+        assert!(reg.contains_type(TypeId::of::<B>()));
+        assert_eq!(vec![TypeId::of::<A>(), TypeId::of::<B>()], reg.order());
+
+        // Present layers are not constructed again.
+        assert_eq!(Ok(()), reg.warm(&[&A_CTX, &B_CTX]));
+        assert_eq!(2, CONSTRUCTED.load(Ordering::SeqCst));
+
+        // D is never added, so C can not be constructed.
+        assert_eq!(
+            Err(ResolveError {
+                unresolved: vec![TypeId::of::<C>()]
+            }),
+            reg.warm(&[&C_CTX])
+        );
+        assert!(!reg.contains_type(TypeId::of::<C>()));
+    }
+
+    #[test]
+    fn resolver_tiebreak()
+    {
+        static A_CTX: StaticContext = static_context!(A);
+        static B_CTX: StaticContext = static_context!(B);
+        static C_CTX: StaticContext = static_context!(C, [B]);
+
+        struct A;
+        struct B;
+        struct C;
+
+        impl LayerConstruct for A
+        {
+            fn construct(_registry: &Registry) -> Self
+            {
+                Self
+            }
+        }
+
+        impl LayerConstruct for B
+        {
+            fn construct(_registry: &Registry) -> Self
+            {
+                Self
+            }
+        }
+
+        impl LayerConstruct for C
+        {
+            fn construct(_registry: &Registry) -> Self
+            {
+                Self
+            }
+        }
+
+        let name = |type_id: TypeId| {
+            match type_id
+            {
+                id if id == TypeId::of::<A>() => "A",
+                id if id == TypeId::of::<B>() => "B",
+                _ => "C",
+            }
+        };
+
+        // Reverse alphabetical order, independent of the order the contexts are added in.
+        let by_name = |a: TypeId, b: TypeId| name(b).cmp(name(a));
+
+        let first = Resolver::new()
+            .add_ctx(&A_CTX)
+            .add_ctx(&C_CTX)
+            .add_ctx(&B_CTX)
+            .with_tiebreak(by_name)
+            .build_reg()
+            .unwrap();
+
+        let second = Resolver::new()
+            .add_ctx(&B_CTX)
+            .add_ctx(&A_CTX)
+            .add_ctx(&C_CTX)
+            .with_tiebreak(by_name)
+            .build_reg()
+            .unwrap();
+
+        let expected = vec![TypeId::of::<B>(), TypeId::of::<C>(), TypeId::of::<A>()];
+        assert_eq!(expected, first.order());
+        assert_eq!(expected, second.order());
+    }
+
+    #[test]
+    fn topological_dispatch()
+    {
+        type Log = RefCell<Vec<&'static str>>;
+
+        static DB_CTX: StaticContext<Log> = static_context!(Db);
+        struct Db;
+
+        impl SimpleDispatch<Log> for Db
+        {
+            fn simple_dispatch(&self, event: &Log) -> Outcome<()>
+            {
+                event.borrow_mut().push("Db");
+
+                Outcome::Handled(())
+            }
+        }
+
+        impl LayerConstruct<Log> for Db
+        {
+            fn construct(_registry: &Registry<Log>) -> Self
+            {
+                Self
+            }
+        }
+
+        static USERS_CTX: StaticContext<Log> = static_context!(Users, [Db]);
+        struct Users
+        {
+            _db: Layer<Db>,
+        }
+
+        impl SimpleDispatch<Log> for Users
+        {
+            fn simple_dispatch(&self, event: &Log) -> Outcome<()>
+            {
+                event.borrow_mut().push("Users");
+
+                Outcome::Handled(())
+            }
+        }
+
+        impl LayerConstruct<Log> for Users
+        {
+            fn construct(registry: &Registry<Log>) -> Self
+            {
+                Self {
+                    _db: registry.get_unchecked(),
+                }
+            }
+        }
+
+        let reg = Resolver::new()
+            .add_ctx(&USERS_CTX)
+            .add_ctx(&DB_CTX)
+            .build_reg()
+            .unwrap();
+
+        let log = Log::default();
+        reg.dispatch(&log);
+
+        assert_eq!(vec!["Db", "Users"], *log.borrow());
+        assert_eq!(vec![TypeId::of::<Db>(), TypeId::of::<Users>()], reg.order());
+    }
+
     #[test]
     fn no_dispatch_reg()
     {
@@ -148,9 +1063,11 @@ mod tests
         where
             T: Display,
         {
-            fn simple_dispatch(&self, event: &T)
+            fn simple_dispatch(&self, event: &T) -> Outcome<()>
             {
                 println!("{}: {}", self.0, event);
+
+                Outcome::Handled(())
             }
         }
 
@@ -164,10 +1081,36 @@ mod tests
 
         let reg = Resolver::new().add_ctx(&A_CTX).build_reg().unwrap();
 
-        print!("\n");
+        println!();
         reg.dispatch(&"Scissors out !!!");
     }
 
+    #[test]
+    fn scoped_registry()
+    {
+        struct Tally<'a>(&'a Cell<usize>);
+
+        impl SimpleDispatch<usize> for Tally<'_>
+        {
+            fn simple_dispatch(&self, event: &usize) -> Outcome<()>
+            {
+                self.0.set(self.0.get() + event);
+
+                Outcome::Handled(())
+            }
+        }
+
+        let tally = Cell::new(0);
+
+        let reg = ScopedRegistry::new();
+        assert_eq!(0, reg.insert(Tally(&tally)));
+        assert_eq!(1, reg.insert(Tally(&tally)));
+
+        assert_eq!(2, reg.len());
+        assert_eq!(2, reg.dispatch(&3).len());
+        assert_eq!(6, tally.get());
+    }
+
     #[cfg(feature = "macro")]
     #[test]
     fn proc_layer()
@@ -210,7 +1153,7 @@ mod tests
 
         impl SimpleDispatch<Action> for Gwen
         {
-            fn simple_dispatch(&self, event: &Action)
+            fn simple_dispatch(&self, event: &Action) -> Outcome<()>
             {
                 match event
                 {
@@ -220,12 +1163,140 @@ mod tests
                         self.chogath.attack(self.dmg);
                     }
                 }
+
+                Outcome::Handled(())
             }
         }
 
         let reg = proc_layer::build_reg!(Gwen, Chogath);
 
-        print!("\n");
+        println!();
         reg.dispatch(&Action::Fight);
     }
+
+    #[cfg(feature = "macro")]
+    #[test]
+    fn layer_or_default()
+    {
+        #[proc_layer::service]
+        struct Config<usize>
+        {
+            #[value = 39]
+            level: usize,
+        }
+
+        impl Default for Config
+        {
+            fn default() -> Self
+            {
+                Self { level: 3 }
+            }
+        }
+
+        #[proc_layer::service]
+        struct Plugin<usize>
+        {
+            #[layer_or_default]
+            config: Config,
+        }
+
+        impl SimpleDispatch<usize> for Config {}
+        impl SimpleDispatch<usize> for Plugin {}
+
+        // The dependency is absent, so the plugin falls back to a default config.
+        let reg = Resolver::new()
+            .with_missing_policy(MissingPolicy::ReturnDefault)
+            .add_ctx(&PLUGIN)
+            .build_reg()
+            .unwrap();
+
+        assert_eq!(3, reg.get::<Plugin>().unwrap().config.level);
+        assert!(reg.get::<Config>().is_none());
+
+        // A resolved dependency is constructed first, even though the plugin could do without it.
+        let reg = Resolver::new()
+            .add_ctx(&PLUGIN)
+            .add_ctx(&CONFIG)
+            .build_reg()
+            .unwrap();
+
+        assert_eq!(39, reg.get::<Plugin>().unwrap().config.level);
+    }
+
+    #[cfg(feature = "macro")]
+    #[test]
+    fn registry_module()
+    {
+        #[proc_layer::registry]
+        mod services
+        {
+            use crate::prelude::*;
+            use std::cell::RefCell;
+
+            pub type Log = RefCell<Vec<&'static str>>;
+
+            #[proc_layer::service]
+            pub struct Storage<Log>
+            {
+                #[value = "Storage"]
+                name: &'static str,
+            }
+
+            #[proc_layer::service]
+            pub struct Cache<Log>
+            {
+                #[layer]
+                _storage: Storage,
+
+                #[value = "Cache"]
+                name: &'static str,
+            }
+
+            #[proc_layer::service(API_CTX)]
+            pub struct Api<Log>
+            {
+                #[layer]
+                _cache: Cache,
+
+                #[value = "Api"]
+                name: &'static str,
+            }
+
+            impl SimpleDispatch<Log> for Storage
+            {
+                fn simple_dispatch(&self, event: &Log) -> Outcome<()>
+                {
+                    event.borrow_mut().push(self.name);
+
+                    Outcome::Handled(())
+                }
+            }
+
+            impl SimpleDispatch<Log> for Cache
+            {
+                fn simple_dispatch(&self, event: &Log) -> Outcome<()>
+                {
+                    event.borrow_mut().push(self.name);
+
+                    Outcome::Handled(())
+                }
+            }
+
+            impl SimpleDispatch<Log> for Api
+            {
+                fn simple_dispatch(&self, event: &Log) -> Outcome<()>
+                {
+                    event.borrow_mut().push(self.name);
+
+                    Outcome::Handled(())
+                }
+            }
+        }
+
+        let reg = services::build();
+        let log = services::Log::default();
+
+        assert_eq!(3, reg.dispatch(&log).len());
+        assert_eq!(vec!["Storage", "Cache", "Api"], *log.borrow());
+    }
 }
@@ -0,0 +1,71 @@
+use crate::{dispatch::Outcome, registry::Registry};
+use plugmap::{IdentityState, PlugMap};
+use std::any::{Any, TypeId};
+
+
+/// Routes events to one of multiple registries, based on the type of the event.
+///
+/// Each registered `Registry<E, Err, Res>` is stored by the `TypeId` of its event type `E`.
+pub struct MultiRegistry<Err = (), Res = ()>
+{
+    map: PlugMap<TypeId, Box<dyn Any>, IdentityState>,
+    _marker: std::marker::PhantomData<fn() -> (Err, Res)>,
+}
+
+
+impl<Err, Res> Clone for MultiRegistry<Err, Res>
+{
+    fn clone(&self) -> Self
+    {
+        Self {
+            map: self.map.clone(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+}
+
+
+impl<Err: 'static, Res: 'static> MultiRegistry<Err, Res>
+{
+    pub fn new() -> Self
+    {
+        Self {
+            map: PlugMap::new_with_identity_hasher(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Registers `reg` for events of type `E`, replacing any registry previously registered for `E`.
+    pub fn register<E: 'static>(&self, reg: Registry<E, Err, Res>)
+    {
+        self.map
+            .insert(TypeId::of::<E>(), Box::new(reg) as Box<dyn Any>);
+    }
+
+    /// Returns the registry registered for events of type `E`.
+    pub fn registry<E: 'static>(&self) -> Option<Registry<E, Err, Res>>
+    {
+        self.map
+            .get(&TypeId::of::<E>())?
+            .downcast_ref::<Registry<E, Err, Res>>()
+            .cloned()
+    }
+
+    /// Dispatches `event` to the registry registered for events of type `E`.
+    ///
+    /// Returns `None` if no such registry exists.
+    pub fn dispatch<E: 'static>(&self, event: &E) -> Option<Vec<Result<Outcome<Res>, Err>>>
+    {
+        let reg = self.map.get(&TypeId::of::<E>())?;
+        Some(reg.downcast_ref::<Registry<E, Err, Res>>()?.dispatch(event))
+    }
+}
+
+
+impl<Err: 'static, Res: 'static> Default for MultiRegistry<Err, Res>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
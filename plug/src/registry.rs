@@ -1,15 +1,115 @@
-use crate::dispatch::{LayerDispatch, NoDispatch};
-use keep::{Guard, Heap};
-use plugmap::PlugMap;
-use std::any::TypeId;
+use crate::{
+    dispatch::{AnyLayer, DispatchReport, LayerDispatch, NoDispatch, Outcome},
+    layer_context::LayerContext,
+    resolver::ResolveError,
+};
+use keep::{Guard, Keep};
+use plugmap::{IdentityState, PlugMap};
+use std::{any::TypeId, cell::RefCell, marker::PhantomData};
+
+#[cfg(feature = "threads")]
+use crate::dispatch::DispatchError;
+#[cfg(feature = "threads")]
+use std::{
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::Duration,
+};
 
 
 pub type Layer<T> = Guard<Box<T>>;
+pub type BoxedLayer<E, Err = (), Res = ()> = Box<dyn AnyLayer<E, Error = Err, Response = Res>>;
+
+
+/// An object safe view of a `Registry`.
+///
+/// `Registry` has generic methods, so it can't be used as a trait object itself.
+/// `&dyn RegistryHandle` can be passed across plugin boundaries instead.
+pub trait RegistryHandle<E = NoDispatch, Err = (), Res = ()>
+{
+    /// Dispatches `event` to all layers, see `Registry::dispatch`.
+    fn dispatch(&self, event: &E) -> Vec<Result<Outcome<Res>, Err>>;
+
+    /// Returns the number of layers in the registry.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    /// Returns `true` if a layer with the `TypeId` `type_id` is present.
+    fn contains_type(&self, type_id: TypeId) -> bool;
+}
+
+
+/// Returned by `Registry::set_order` if the order does not list every layer exactly once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderError
+{
+    /// Listed `TypeId`s without a layer in the registry.
+    pub unknown: Vec<TypeId>,
+
+    /// Layers in the registry which were not listed.
+    pub missing: Vec<TypeId>,
+
+    /// `TypeId`s listed more than once.
+    pub duplicated: Vec<TypeId>,
+}
+
+
+/// What `Registry::get_or_default` does if the requested layer is not present.
+///
+/// The policy only applies to `get_or_default`. The `construct` generated by the `service` macro uses it
+/// for fields marked `#[layer_or_default]`, while `#[layer]` fields need not be `Default` and are
+/// fetched through `get_unchecked`, which panics on a missing layer regardless of the policy.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MissingPolicy
+{
+    /// Panic, like `Registry::get_unchecked`.
+    #[default]
+    Panic,
+
+    /// Fall back to a default constructed layer, which is not inserted into the registry.
+    ReturnDefault,
+}
+
+
+/// The key a layer is stored under, the `TypeId` of the layer along with an index.
+///
+/// The index is zero for all layers, except for the ones of `Registry::with_dummy_layers`,
+/// which lets them share the `TypeId` of their template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct LayerKey
+{
+    type_id: TypeId,
+    index: usize,
+}
+
+
+impl LayerKey
+{
+    fn new(type_id: TypeId) -> Self
+    {
+        Self { type_id, index: 0 }
+    }
+}
 
 
 pub struct Registry<E = NoDispatch, Err = (), Res = ()>
 {
-    map: PlugMap<TypeId, Box<dyn LayerDispatch<E, Error = Err, Response = Res>>>,
+    // TypeIds already are hashes, so hashing them again is wasted work.
+    // Layers are stored tagged with their concrete type, so `get` can downcast them.
+    map: PlugMap<LayerKey, Guard<BoxedLayer<E, Err, Res>>, IdentityState>,
+
+    // The order layers were first inserted in, which is the order they are dispatched to.
+    // Since a Resolver inserts dependencies before their dependents, this is a topological order.
+    order: Keep<Vec<LayerKey>>,
+
+    missing_policy: Keep<MissingPolicy>,
 }
 
 
@@ -19,6 +119,21 @@ impl<E, Err, Res> Clone for Registry<E, Err, Res>
     {
         Self {
             map: self.map.clone(),
+            order: self.order.clone(),
+            missing_policy: self.missing_policy.clone(),
+        }
+    }
+}
+
+
+impl Registry
+{
+    /// Returns a builder for a registry dispatching events of type `E`.
+    pub fn builder<E, Err, Res>() -> RegistryBuilder<E, Err, Res>
+    {
+        RegistryBuilder {
+            capacity: None,
+            _marker: PhantomData,
         }
     }
 }
@@ -29,30 +144,80 @@ impl<E, Err, Res> Registry<E, Err, Res>
     pub fn new() -> Self
     {
         Self {
-            map: PlugMap::new(),
+            map: PlugMap::new_with_identity_hasher(),
+            order: Keep::new(vec![]),
+            missing_policy: Keep::new(MissingPolicy::default()),
         }
     }
 
+    /// Returns the number of layers the registry can hold without resizing.
+    pub fn capacity(&self) -> usize
+    {
+        self.map.capacity()
+    }
+
+    /// Returns the `TypeId`s of all layers in dispatch order.
+    pub fn order(&self) -> Vec<TypeId>
+    {
+        self.order.read().iter().map(|key| key.type_id).collect()
+    }
+
+    /// Returns the number of layers in the registry.
+    pub fn len(&self) -> usize
+    {
+        self.order.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    /// Returns `true` if a layer with the `TypeId` `type_id` is present.
+    pub fn contains_type(&self, type_id: TypeId) -> bool
+    {
+        self.map.get(&LayerKey::new(type_id)).is_some()
+    }
+
     pub fn insert<T>(&self, layer: T)
     where
         T: LayerDispatch<E, Error = Err, Response = Res> + 'static,
     {
-        let layer: Heap<Box<dyn LayerDispatch<E, Error = Err, Response = Res>>> =
-            unsafe { Heap::from_ptr(Box::into_raw(Box::new(Box::new(layer)))) };
+        self.replace_or_insert(layer);
+    }
 
-        self.map.insert(TypeId::of::<T>(), layer);
+    /// Inserts `layer` and returns the layer of type `T` it replaced, or `None` if there was none.
+    ///
+    /// The new layer takes the dispatch slot of the replaced one, which stays usable
+    /// for as long as the returned guard is alive. The `on_remove` hook of the replaced
+    /// layer is called once it was replaced.
+    pub fn replace_or_insert<T>(&self, layer: T) -> Option<Layer<T>>
+    where
+        T: LayerDispatch<E, Error = Err, Response = Res> + 'static,
+    {
+        let layer: BoxedLayer<E, Err, Res> = Box::new(layer);
+
+        let key = LayerKey::new(TypeId::of::<T>());
+        let old = self.map.insert(key, Self::tagged(layer));
+        self.push_order(key);
+
+        let old = (*old?.read()).clone();
+        (**old).on_remove();
+
+        old.downcast().ok()
     }
 
+    /// Returns the layer of type `T`, if present.
     pub fn get<T>(&self) -> Option<Layer<T>>
     where
         T: LayerDispatch<E, Error = Err, Response = Res> + 'static,
     {
-        self.map.get(&TypeId::of::<T>()).map(|f| unsafe {
-            std::mem::transmute::<
-                Guard<Box<dyn LayerDispatch<E, Error = Err, Response = Res>>>,
-                Guard<Box<T>>,
-            >(f)
-        })
+        // The key alone is not enough, `insert_by` might have been given a wrong `TypeId`,
+        // but the layer is tagged with its actual type.
+        (*self.map.get(&LayerKey::new(TypeId::of::<T>()))?)
+            .clone()
+            .downcast()
+            .ok()
     }
 
     pub fn get_unchecked<T>(&self) -> Layer<T>
@@ -62,30 +227,366 @@ impl<E, Err, Res> Registry<E, Err, Res>
         self.get::<T>().expect("Layer was not present in map")
     }
 
+    /// Like `get_unchecked`, but a missing layer is handled according to the registry's `MissingPolicy`.
+    ///
+    /// This lets layers be constructed even if some of their dependencies failed to load.
+    pub fn get_or_default<T>(&self) -> Layer<T>
+    where
+        T: Default + LayerDispatch<E, Error = Err, Response = Res> + 'static,
+    {
+        if let Some(layer) = self.get::<T>()
+        {
+            return layer;
+        }
+
+        match *self.missing_policy.read()
+        {
+            MissingPolicy::Panic => panic!("Layer was not present in map"),
+            MissingPolicy::ReturnDefault => Keep::new(Box::new(T::default())).into_guard(),
+        }
+    }
+
+    /// Sets what `get_or_default` does if the requested layer is not present, shared by all clones.
+    pub fn set_missing_policy(&self, policy: MissingPolicy)
+    {
+        self.missing_policy.write(policy);
+    }
+
     /// Inserts a boxed layer into the registry
     ///
     /// # Safety
     /// The caller must ensure that `type_id` matches the actual TypeId of the
-    /// boxed type of `layer`.
-    pub unsafe fn insert_by(
-        &self,
-        layer: Box<dyn LayerDispatch<E, Error = Err, Response = Res> + 'static>,
-        type_id: TypeId,
-    )
+    /// boxed type of `layer`. A mismatched layer is never returned by `get`,
+    /// but it still takes the dispatch slot and place of the layer with that `TypeId`.
+    pub unsafe fn insert_by(&self, layer: BoxedLayer<E, Err, Res>, type_id: TypeId)
+    {
+        self.insert_keyed(LayerKey::new(type_id), layer);
+    }
+
+    fn insert_keyed(&self, key: LayerKey, layer: BoxedLayer<E, Err, Res>)
+    {
+        self.map.insert(key, Self::tagged(layer));
+        self.push_order(key);
+    }
+
+    /// Returns a guard to `layer`, tagged with its concrete type for `Guard::downcast`.
+    fn tagged(layer: BoxedLayer<E, Err, Res>) -> Guard<BoxedLayer<E, Err, Res>>
+    {
+        let type_id = (*layer).as_any().type_id();
+
+        // SAFETY: `as_any` returns the layer as its concrete type.
+        unsafe { Keep::new_tagged(layer, type_id) }.into_guard()
+    }
+
+    /// Creates a registry from already constructed layers, skipping dependency resolution.
+    ///
+    /// Layers are dispatched to in the order they are given in.
+    ///
+    /// # Safety
+    /// Same as `Registry::insert_by`, each `TypeId` must match the boxed type of its layer.
+    pub unsafe fn from_layers(layers: Vec<(TypeId, BoxedLayer<E, Err, Res>)>) -> Self
+    {
+        let reg = Self::new();
+
+        for (type_id, layer) in layers
+        {
+            unsafe { reg.insert_by(layer, type_id) };
+        }
+
+        reg
+    }
+
+    /// Creates a registry holding `count` clones of `template`, for measuring how dispatching
+    /// scales with the number of layers.
+    ///
+    /// The clones are dispatched to and listed by `order` under the `TypeId` of `T`,
+    /// but they are stored apart from a layer of type `T`, so `get` never returns them.
+    #[cfg(feature = "test-hooks")]
+    pub fn with_dummy_layers<T>(count: usize, template: T) -> Self
+    where
+        T: Clone + LayerDispatch<E, Error = Err, Response = Res> + 'static,
+    {
+        let reg = Registry::builder().capacity(count).build();
+
+        for index in 1..=count
+        {
+            let key = LayerKey {
+                type_id: TypeId::of::<T>(),
+                index,
+            };
+
+            reg.insert_keyed(key, Box::new(template.clone()));
+        }
+
+        reg
+    }
+
+    /// Constructs and inserts the layers of all `ctxs` which are not present yet,
+    /// so the cost of constructing them is not paid later on.
+    ///
+    /// A layer is constructed once all of its dependencies are present, regardless of the order of `ctxs`.
+    /// Layers whose dependencies are missing or cyclic are not constructed and returned as an error.
+    pub fn warm(&self, ctxs: &[&LayerContext<E, Err, Res>]) -> Result<(), ResolveError>
+    {
+        let mut pending: Vec<_> = ctxs
+            .iter()
+            .filter(|ctx| !self.contains_type(ctx.id()))
+            .collect();
+
+        loop
+        {
+            let before = pending.len();
+
+            pending.retain(|ctx| {
+                if !ctx.deps().iter().all(|dep| self.contains_type(*dep))
+                {
+                    return true;
+                }
+
+                ctx.insert_into_reg(self);
+                false
+            });
+
+            if pending.is_empty()
+            {
+                return Ok(());
+            }
+
+            if pending.len() == before
+            {
+                return Err(ResolveError {
+                    unresolved: pending.iter().map(|ctx| ctx.id()).collect(),
+                });
+            }
+        }
+    }
+
+    /// Replaces the dispatch order, `order` has to list the `TypeId` of every layer exactly once.
+    ///
+    /// Unlike the order the layers were inserted in, a custom order might dispatch to dependents
+    /// before their dependencies. If `order` is invalid, the dispatch order is left untouched.
+    pub fn set_order(&self, order: Vec<TypeId>) -> Result<(), OrderError>
+    {
+        let order: Vec<_> = order.into_iter().map(LayerKey::new).collect();
+        let mut current = self.order.read();
+
+        loop
+        {
+            let mut duplicated = vec![];
+
+            for (i, key) in order.iter().enumerate()
+            {
+                if order[..i].contains(key) && !duplicated.contains(&key.type_id)
+                {
+                    duplicated.push(key.type_id);
+                }
+            }
+
+            let error = OrderError {
+                unknown: order
+                    .iter()
+                    .filter(|key| !current.contains(key))
+                    .map(|key| key.type_id)
+                    .collect(),
+                missing: current
+                    .iter()
+                    .filter(|key| !order.contains(key))
+                    .map(|key| key.type_id)
+                    .collect(),
+                duplicated,
+            };
+
+            if !error.unknown.is_empty()
+                || !error.missing.is_empty()
+                || !error.duplicated.is_empty()
+            {
+                return Err(error);
+            }
+
+            // Retries if a layer was inserted in the meantime, which would be missing from `order`.
+            match self.order.exchange(&current, order.clone())
+            {
+                Ok(_) => return Ok(()),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Dispatches `event` to all layers, dependencies are dispatched to before their dependents.
+    ///
+    /// A layer dispatching to this registry (or one of its clones) again from within its
+    /// `layer_dispatch` gets no results back, instead of recursing through all layers again.
+    pub fn dispatch(&self, event: &E) -> Vec<Result<Outcome<Res>, Err>>
+    {
+        let Some(_dispatching) = Dispatching::enter(self.order.as_ptr())
+        else
+        {
+            return vec![];
+        };
+
+        let order = self.order.read();
+        let mut results = Vec::with_capacity(order.len());
+
+        for key in order.iter()
+        {
+            if let Some(layer) = self.map.get(key)
+            {
+                results.push((***layer).layer_dispatch(event));
+            }
+        }
+
+        results
+    }
+
+    /// Dispatches `event` to all layers and counts how many handled, skipped or failed it.
+    pub fn dispatch_report(&self, event: &E) -> DispatchReport
+    {
+        self.dispatch(event).iter().collect()
+    }
+
+    /// Dispatches `event` to all layers, pairing each result with the `TypeId` of the layer that produced it.
+    ///
+    /// Like `dispatch`, a nested call from within a layer returns no results.
+    pub fn dispatch_map(&self, event: &E) -> Vec<(TypeId, Result<Outcome<Res>, Err>)>
     {
-        let layer: Heap<Box<dyn LayerDispatch<E, Error = Err, Response = Res>>> =
-            unsafe { Heap::from_ptr(Box::into_raw(Box::new(layer))) };
+        let Some(_dispatching) = Dispatching::enter(self.order.as_ptr())
+        else
+        {
+            return vec![];
+        };
+
+        let order = self.order.read();
+        let mut results = Vec::with_capacity(order.len());
+
+        for key in order.iter()
+        {
+            if let Some(layer) = self.map.get(key)
+            {
+                results.push((key.type_id, (***layer).layer_dispatch(event)));
+            }
+        }
 
-        self.map.insert(type_id, layer);
+        results
     }
 
-    pub fn dispatch(&self, event: &E) -> Vec<Result<Res, Err>>
+    /// Dispatches `event` to all layers like `dispatch`, handing each response to `sink` by reference
+    /// together with the `TypeId` of the layer that produced it, instead of collecting them.
+    ///
+    /// Errors are not handed to `sink`, they are only counted in the returned report.
+    pub fn dispatch_writing(&self, event: &E, sink: &mut impl FnMut(TypeId, &Res))
+    -> DispatchReport
     {
+        let mut report = DispatchReport::default();
+
+        let Some(_dispatching) = Dispatching::enter(self.order.as_ptr())
+        else
+        {
+            return report;
+        };
+
+        for key in self.order.read().iter()
+        {
+            if let Some(layer) = self.map.get(key)
+            {
+                match (***layer).layer_dispatch_into(event, &mut |res| sink(key.type_id, res))
+                {
+                    Ok(Outcome::Handled(())) => report.handled += 1,
+                    Ok(Outcome::Skipped) => report.skipped += 1,
+                    Err(_) => report.failed += 1,
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Removes all layers, dependents before their dependencies.
+    ///
+    /// Each layer's `on_remove` hook is called right before it is removed, so a layer
+    /// can still use its dependencies while shutting down.
+    pub fn shutdown(&self)
+    {
+        let order = self.order.swap(vec![]);
+
+        for key in order.iter().rev()
+        {
+            if let Some(layer) = self.map.get(key)
+            {
+                (***layer).on_remove();
+                self.map.remove(key);
+            }
+        }
+    }
+
+    /// Appends `key` to the dispatch order, unless it is already part of it.
+    fn push_order(&self, key: LayerKey)
+    {
+        let mut current = self.order.read();
+
+        loop
+        {
+            if current.contains(&key)
+            {
+                return;
+            }
+
+            let mut order = current.to_vec();
+            order.push(key);
+
+            match self.order.exchange(&current, order)
+            {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+
+#[cfg(feature = "threads")]
+impl<E, Err, Res> Registry<E, Err, Res>
+where
+    E: Clone + Send + Sync + 'static,
+    Err: Send + 'static,
+    Res: Send + 'static,
+{
+    /// Dispatches `event` to all layers like `dispatch`, running each layer on its own thread.
+    ///
+    /// A layer which does not return within `per_layer` is reported as `DispatchError::TimedOut`
+    /// and left running in the background, its eventual result is discarded.
+    pub fn dispatch_with_timeout(
+        &self,
+        event: &E,
+        per_layer: Duration,
+    ) -> Vec<Result<Outcome<Res>, DispatchError<Err>>>
+    {
+        let event = Arc::new(event.clone());
         let mut results = vec![];
 
-        for layer in &self.map
+        for key in self.order.read().iter().copied()
         {
-            results.push(layer.as_ref().as_ref().layer_dispatch(event));
+            let (sender, receiver) = mpsc::channel();
+            let reg = self.clone();
+            let event = event.clone();
+
+            thread::spawn(move || {
+                if let Some(layer) = reg.map.get(&key)
+                {
+                    let _ = sender.send((***layer).layer_dispatch(&event));
+                }
+            });
+
+            match receiver.recv_timeout(per_layer)
+            {
+                Ok(result) => results.push(result.map_err(DispatchError::Layer)),
+                Err(RecvTimeoutError::Timeout) =>
+                {
+                    results.push(Err(DispatchError::TimedOut(key.type_id)))
+                }
+
+                // The layer was removed or panicked, like `dispatch` this yields no result.
+                Err(RecvTimeoutError::Disconnected) => (),
+            }
         }
 
         results
@@ -93,6 +594,57 @@ impl<E, Err, Res> Registry<E, Err, Res>
 }
 
 
+/// Builds a `Registry`, see `Registry::builder`.
+pub struct RegistryBuilder<E, Err, Res>
+{
+    capacity: Option<usize>,
+    _marker: PhantomData<BoxedLayer<E, Err, Res>>,
+}
+
+
+impl<E, Err, Res> RegistryBuilder<E, Err, Res>
+{
+    /// Prepares the registry to hold `capacity` layers without resizing.
+    pub fn capacity(mut self, capacity: usize) -> Self
+    {
+        self.capacity = Some(capacity);
+        self
+    }
+
+    pub fn build(self) -> Registry<E, Err, Res>
+    {
+        Registry {
+            map: match self.capacity
+            {
+                Some(capacity) => PlugMap::with_capacity_and_identity_hasher(capacity),
+                None => PlugMap::new_with_identity_hasher(),
+            },
+            order: Keep::new(vec![]),
+            missing_policy: Keep::new(MissingPolicy::default()),
+        }
+    }
+}
+
+
+impl<E, Err, Res> RegistryHandle<E, Err, Res> for Registry<E, Err, Res>
+{
+    fn dispatch(&self, event: &E) -> Vec<Result<Outcome<Res>, Err>>
+    {
+        Registry::dispatch(self, event)
+    }
+
+    fn len(&self) -> usize
+    {
+        Registry::len(self)
+    }
+
+    fn contains_type(&self, type_id: TypeId) -> bool
+    {
+        Registry::contains_type(self, type_id)
+    }
+}
+
+
 impl<E, Err, Resp> Default for Registry<E, Err, Resp>
 {
     fn default() -> Self
@@ -100,3 +652,40 @@ impl<E, Err, Resp> Default for Registry<E, Err, Resp>
         Self::new()
     }
 }
+
+
+thread_local! {
+    // The registries the current thread is dispatching to, identified by their shared order.
+    static DISPATCHING: RefCell<Vec<*const ()>> = const { RefCell::new(vec![]) };
+}
+
+
+/// Marks a registry as being dispatched to by the current thread until dropped.
+struct Dispatching(*const ());
+
+
+impl Dispatching
+{
+    /// Returns `None` if the current thread is already dispatching to the registry `id`.
+    fn enter(id: *const ()) -> Option<Self>
+    {
+        DISPATCHING.with_borrow_mut(|dispatching| {
+            if dispatching.contains(&id)
+            {
+                return None;
+            }
+
+            dispatching.push(id);
+            Some(Self(id))
+        })
+    }
+}
+
+
+impl Drop for Dispatching
+{
+    fn drop(&mut self)
+    {
+        DISPATCHING.with_borrow_mut(|dispatching| dispatching.retain(|id| *id != self.0));
+    }
+}
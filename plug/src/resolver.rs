@@ -1,5 +1,9 @@
-use crate::{dispatch::NoDispatch, layer_context::LayerContext, registry::Registry};
-use std::any::TypeId;
+use crate::{
+    dispatch::NoDispatch,
+    layer_context::LayerContext,
+    registry::{MissingPolicy, Registry},
+};
+use std::{any::TypeId, cmp::Ordering};
 
 
 struct Ctx<'a, E, Err, Res>
@@ -9,9 +13,24 @@ struct Ctx<'a, E, Err, Res>
 }
 
 
+/// Returned by `Resolver::build` if not all layers could be resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveError
+{
+    /// The layers whose dependencies are missing or cyclic.
+    pub unresolved: Vec<TypeId>,
+}
+
+
 pub struct Resolver<'a, E = NoDispatch, Err = (), Res = ()>
 {
     layers: Vec<Ctx<'a, E, Err, Res>>,
+
+    // Orders layers whose dependencies are resolved at the same time.
+    tiebreak: Option<Box<dyn Fn(TypeId, TypeId) -> Ordering + 'a>>,
+
+    // The policy of built registries, already in effect while their layers are constructed.
+    missing_policy: MissingPolicy,
 }
 
 
@@ -19,7 +38,11 @@ impl<'a, E, Err, Res> Resolver<'a, E, Err, Res>
 {
     pub fn new() -> Self
     {
-        Resolver { layers: Vec::new() }
+        Resolver {
+            layers: Vec::new(),
+            tiebreak: None,
+            missing_policy: MissingPolicy::default(),
+        }
     }
 
     pub fn add_ctx(mut self, layer: &'a LayerContext<E, Err, Res>) -> Self
@@ -31,20 +54,77 @@ impl<'a, E, Err, Res> Resolver<'a, E, Err, Res>
         self
     }
 
-    pub fn build_reg(mut self) -> Option<Registry<E, Err, Res>>
+    /// Resolves layers that are ready at the same time in the order given by `tiebreak`,
+    /// instead of the order they were added in.
+    pub fn with_tiebreak(mut self, tiebreak: impl Fn(TypeId, TypeId) -> Ordering + 'a) -> Self
+    {
+        self.tiebreak = Some(Box::new(tiebreak));
+        self
+    }
+
+    /// Sets the `MissingPolicy` of built registries, which layers use while they are constructed.
+    pub fn with_missing_policy(mut self, policy: MissingPolicy) -> Self
+    {
+        self.missing_policy = policy;
+        self
+    }
+
+    pub fn build_reg(self) -> Option<Registry<E, Err, Res>>
+    {
+        self.build().ok()
+    }
+
+    /// Like `build_reg`, but leaves the resolver untouched, so it can build any number of registries.
+    pub fn build(&self) -> Result<Registry<E, Err, Res>, ResolveError>
     {
         let reg = Registry::new();
+        reg.set_missing_policy(self.missing_policy);
+
+        let mut layers: Vec<_> = self
+            .layers
+            .iter()
+            .map(|Ctx { layer, deps }| {
+                // Optional dependencies only have to be waited for if this resolver constructs them.
+                let optional = layer
+                    .optional_deps()
+                    .iter()
+                    .filter(|dep| self.layers.iter().any(|ctx| ctx.layer.id() == **dep));
+
+                Ctx {
+                    layer: *layer,
+                    deps: deps.iter().chain(optional).copied().collect(),
+                }
+            })
+            .collect();
 
         loop
         {
             let mut resolved = None;
 
-            for (i, Ctx { deps, .. }) in &mut self.layers.iter_mut().enumerate()
+            for (i, Ctx { deps, layer }) in layers.iter().enumerate()
             {
-                if deps.is_empty()
+                if !deps.is_empty()
+                {
+                    continue;
+                }
+
+                let Some(tiebreak) = &self.tiebreak
+                else
                 {
                     resolved = Some(i);
                     break;
+                };
+
+                // Pick the smallest of all ready layers.
+                let smaller = match resolved
+                {
+                    Some(best) => tiebreak(layer.id(), layers[best].layer.id()).is_lt(),
+                    None => true,
+                };
+
+                if smaller
+                {
+                    resolved = Some(i);
                 }
             }
 
@@ -53,11 +133,11 @@ impl<'a, E, Err, Res> Resolver<'a, E, Err, Res>
                 Some(index) =>
                 {
                     // Remove the resolved layer from the other layers
-                    let resolved_layer = self.layers.remove(index).layer;
+                    let resolved_layer = layers.remove(index).layer;
                     let dep = resolved_layer.id();
 
                     // Remove the resolved layer from the other layers dependencies
-                    for Ctx { deps, .. } in &mut self.layers
+                    for Ctx { deps, .. } in &mut layers
                     {
                         deps.retain(|e| *e != dep);
                     }
@@ -68,12 +148,14 @@ impl<'a, E, Err, Res> Resolver<'a, E, Err, Res>
 
                 None =>
                 {
-                    if self.layers.is_empty()
+                    if layers.is_empty()
                     {
-                        return Some(reg);
+                        return Ok(reg);
                     }
 
-                    return None;
+                    return Err(ResolveError {
+                        unresolved: layers.iter().map(|ctx| ctx.layer.id()).collect(),
+                    });
                 }
             }
         }
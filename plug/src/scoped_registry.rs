@@ -0,0 +1,126 @@
+use crate::dispatch::{LayerDispatch, NoDispatch, Outcome};
+use keep::{Guard, Keep};
+use plugmap::PlugMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+pub type ScopedLayer<'scope, E, Err = (), Res = ()> =
+    Guard<Box<dyn LayerDispatch<E, Error = Err, Response = Res> + 'scope>>;
+
+
+/// A registry for layers that only live for `'scope`.
+///
+/// Unlike `Registry`, layers are not keyed by their `TypeId` (which requires `'static`),
+/// but by the index at which they were inserted. This allows layers borrowing
+/// data from the stack to be dispatched to.
+pub struct ScopedRegistry<'scope, E = NoDispatch, Err = (), Res = ()>
+{
+    map: PlugMap<usize, Box<dyn LayerDispatch<E, Error = Err, Response = Res> + 'scope>>,
+    next_index: Guard<AtomicUsize>,
+    // The indices of all inserted layers, in the order they were published in.
+    order: Keep<Vec<usize>>,
+}
+
+
+impl<E, Err, Res> Clone for ScopedRegistry<'_, E, Err, Res>
+{
+    fn clone(&self) -> Self
+    {
+        Self {
+            map: self.map.clone(),
+            next_index: self.next_index.clone(),
+            order: self.order.clone(),
+        }
+    }
+}
+
+
+impl<'scope, E, Err, Res> ScopedRegistry<'scope, E, Err, Res>
+{
+    pub fn new() -> Self
+    {
+        Self {
+            map: PlugMap::new(),
+            next_index: Keep::new(AtomicUsize::new(0)).read(),
+            order: Keep::new(vec![]),
+        }
+    }
+
+    /// Inserts a layer into the registry and returns the index it was stored at.
+    pub fn insert<T>(&self, layer: T) -> usize
+    where
+        T: LayerDispatch<E, Error = Err, Response = Res> + 'scope,
+    {
+        let layer: Box<Box<dyn LayerDispatch<E, Error = Err, Response = Res> + 'scope>> =
+            Box::new(Box::new(layer));
+
+        let index = self.next_index.fetch_add(1, Ordering::SeqCst);
+        self.map.insert(index, layer);
+
+        // The index is only published once its layer is inserted, so `dispatch` never sees an index
+        // without a layer, and inserts never wait for slower ones.
+        self.push_order(index);
+
+        index
+    }
+
+    /// Tries to get the layer stored at `index`.
+    pub fn get(&self, index: usize) -> Option<ScopedLayer<'scope, E, Err, Res>>
+    {
+        self.map.get(&index)
+    }
+
+    /// Returns the number of layers inserted into this registry.
+    pub fn len(&self) -> usize
+    {
+        self.order.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    /// Dispatches `event` to all layers in insertion order.
+    pub fn dispatch(&self, event: &E) -> Vec<Result<Outcome<Res>, Err>>
+    {
+        let mut results = vec![];
+
+        for &index in self.order.read().iter()
+        {
+            if let Some(layer) = self.get(index)
+            {
+                results.push(layer.as_ref().as_ref().layer_dispatch(event));
+            }
+        }
+
+        results
+    }
+
+    /// Appends `index` to the dispatch order.
+    fn push_order(&self, index: usize)
+    {
+        let mut current = self.order.read();
+
+        loop
+        {
+            let mut order = current.to_vec();
+            order.push(index);
+
+            match self.order.exchange(&current, order)
+            {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+
+impl<E, Err, Res> Default for ScopedRegistry<'_, E, Err, Res>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
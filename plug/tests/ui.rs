@@ -0,0 +1,9 @@
+#![cfg(feature = "macro")]
+
+
+#[test]
+fn ui()
+{
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
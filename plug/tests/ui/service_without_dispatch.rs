@@ -0,0 +1,18 @@
+use plug::prelude::*;
+
+
+enum Action
+{
+    Fight,
+}
+
+
+#[service]
+struct Chogath<Action>
+{
+    #[default]
+    health: usize,
+}
+
+
+fn main() {}
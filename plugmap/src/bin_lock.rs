@@ -0,0 +1,96 @@
+use alloc::boxed::Box;
+use core::{
+    hint,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+
+/// Per-bin spinlocks used as a fallback for bins with pathologically long collision chains.
+///
+/// Bins are only locked once their chain is longer than `threshold`, short chains stay lock-free.
+/// The lock only serializes writers which observed the long chain, correctness still
+/// relies on the lock-free insert and remove of the chain itself.
+pub struct BinLocks
+{
+    threshold: usize,
+    locks: Box<[AtomicBool]>,
+    chain_lens: Box<[AtomicUsize]>,
+}
+
+
+impl BinLocks
+{
+    pub fn new(capacity: usize, threshold: usize) -> Self
+    {
+        Self {
+            threshold,
+            locks: (0..capacity).map(|_| AtomicBool::new(false)).collect(),
+            chain_lens: (0..capacity).map(|_| AtomicUsize::new(0)).collect(),
+        }
+    }
+
+    #[inline]
+    pub fn threshold(&self) -> usize
+    {
+        self.threshold
+    }
+
+    /// Locks the bin at `index` if its chain is longer than the threshold.
+    ///
+    /// The bin stays locked until the returned guard is dropped.
+    pub fn lock_if_long(&self, index: usize) -> Option<BinLockGuard<'_>>
+    {
+        if self.chain_lens[index].load(Ordering::Relaxed) <= self.threshold
+        {
+            return None;
+        }
+
+        let lock = &self.locks[index];
+        let mut spins = 0u32;
+
+        while lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Back off to the scheduler if the lock is held for a while.
+            if spins < 64
+            {
+                spins += 1;
+                hint::spin_loop();
+            }
+            else
+            {
+                crate::yield_now();
+            }
+        }
+
+        Some(BinLockGuard(lock))
+    }
+
+    /// Records that a node was added to the chain of the bin at `index`.
+    #[inline]
+    pub fn grown(&self, index: usize)
+    {
+        self.chain_lens[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a node was removed from the chain of the bin at `index`.
+    #[inline]
+    pub fn shrunk(&self, index: usize)
+    {
+        self.chain_lens[index].fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+
+/// Unlocks its bin when dropped.
+pub struct BinLockGuard<'a>(&'a AtomicBool);
+
+
+impl Drop for BinLockGuard<'_>
+{
+    fn drop(&mut self)
+    {
+        self.0.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,47 @@
+use alloc::vec::Vec;
+use core::ops::Deref;
+use keep::Guard;
+
+
+/// A guard to a `Vec<u8>` value that derefs directly to `[u8]`.
+pub struct GuardedSlice(pub(crate) Guard<Vec<u8>>);
+
+
+impl GuardedSlice
+{
+    /// Returns the underlying guard.
+    #[inline]
+    pub fn into_guard(self) -> Guard<Vec<u8>>
+    {
+        self.0
+    }
+}
+
+
+impl Deref for GuardedSlice
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target
+    {
+        self.0.as_slice()
+    }
+}
+
+
+impl AsRef<[u8]> for GuardedSlice
+{
+    fn as_ref(&self) -> &[u8]
+    {
+        self
+    }
+}
+
+
+impl core::fmt::Debug for GuardedSlice
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        (**self).fmt(f)
+    }
+}
@@ -1,3 +1,6 @@
+use crate::table::Table;
+use alloc::{vec, vec::Vec};
+use core::sync::atomic::{AtomicU8, Ordering};
 use keep::*;
 
 
@@ -5,50 +8,113 @@ pub enum Entry<Key, Val>
 {
     Empty,
     Head(Keep<EntryNode<Key, Val>>),
+
+    /// The bin was migrated to this table during a resize, see `Table::migrate_bin`.
+    ///
+    /// A rehash scatters the entries of a bin over all bins of the table, so it also keeps
+    /// the frozen chain of the bin for the ones walking the bins of the old table.
+    Moved(Forward<Key, Val>, Option<Keep<EntryNode<Key, Val>>>),
 }
 
 
+/// A guard to the table the bins of a resize are migrated to, shared by all of them.
+///
+/// Guards to an immutable `Keep` are only counted instead of registered, so cloning it per bin stays cheap.
+pub type Forward<Key, Val> = Guard<Guard<Table<Key, Val>>>;
+
+
 impl<Key, Val> Entry<Key, Val>
 where
     Key: Eq,
 {
-    pub fn search(&self, key: &Key) -> Option<Guard<Val>>
+    pub fn search(&self, key: &Key, hash: u64) -> Option<Guard<Val>>
     {
         match self
         {
-            Entry::Empty => None,
-            Entry::Head(keep) => keep.read().search(key),
+            Entry::Empty | Entry::Moved(..) => None,
+            Entry::Head(keep) => keep.read().search(key, hash),
         }
     }
 
-    pub fn remove_from_children(&self, key: &Key) -> Option<Keep<Val>>
+    /// Marks the node containing `key` as removed and returns its value.
+    ///
+    /// The node is not unlinked from the chain, see `Table::purge`.
+    pub fn remove(&self, key: &Key) -> Option<Keep<Val>>
     {
         match self
         {
-            Entry::Empty => None,
+            Entry::Empty | Entry::Moved(..) => None,
+            Entry::Head(keep) => keep.read().remove(key),
+        }
+    }
 
-            Entry::Head(keep) =>
-            {
-                todo!("Implement remove");
-                // let current = keep;
+    /// Replaces the value of `key` with `f` applied to it, returns `false` if `key` is not present.
+    pub fn modify(&self, key: &Key, f: impl FnOnce(&Val) -> Val) -> bool
+    {
+        match self
+        {
+            Entry::Empty | Entry::Moved(..) => false,
+            Entry::Head(keep) => keep.read().modify(key, f),
+        }
+    }
 
-                // loop
-                // {}
-            }
+    /// Returns the number of nodes preceding the live node containing `key` in the chain.
+    pub fn position(&self, key: &Key) -> Option<usize>
+    {
+        match self
+        {
+            Entry::Empty | Entry::Moved(..) => None,
+            Entry::Head(keep) => keep.read().position(key),
         }
     }
 
-    pub fn buffered(&self) -> Vec<Guard<Val>>
+    pub fn buffered_pairs(&self) -> Vec<(Guard<Key>, Guard<Val>)>
+    {
+        self.buffered_pairs_where(|_| true)
+    }
+
+    /// Like `buffered_pairs`, but only buffers the entries whose hash satisfies `filter`.
+    pub fn buffered_pairs_where(
+        &self,
+        filter: impl Fn(u64) -> bool,
+    ) -> Vec<(Guard<Key>, Guard<Val>)>
     {
         let mut ret = vec![];
 
         if let Self::Head(head) = self
         {
-            head.read().buffered(&mut ret);
+            head.read().buffered_pairs(&filter, &mut ret);
         }
 
         ret
     }
+
+    /// Like `buffered_pairs_where`, but calls `f` with every entry instead of buffering guards to them.
+    pub fn for_each_where(&self, filter: impl Fn(u64) -> bool, f: &mut impl FnMut(&Key, &Val))
+    {
+        if let Self::Head(head) = self
+        {
+            head.read().for_each(&filter, f);
+        }
+    }
+}
+
+
+/// The outcome of `EntryNode::update`.
+pub enum Update<Val>
+{
+    /// The key was already present and its old value was replaced.
+    Replaced(Keep<Val>),
+
+    /// The key was already present and was left untouched, since replacing was not requested.
+    Present(Guard<Val>),
+
+    /// The key was not present and the node was linked into the chain at its place by hash,
+    /// the chain is now this long counted from the node `update` was called on.
+    Appended(usize),
+
+    /// The chain was modified by a concurrent removal or is being migrated, the update needs to be retried.
+    Retry,
 }
 
 
@@ -58,9 +124,19 @@ pub struct EntryNode<Key, Val>
     key: Guard<Key>,
     hash: u64,
     next: Keep<Option<Keep<EntryNode<Key, Val>>>>,
+
+    // A combination of the `REMOVED` and `FROZEN` flags.
+    state: AtomicU8,
 }
 
 
+/// The node was removed from the map.
+const REMOVED: u8 = 1;
+
+/// The node's bin is being migrated, so the node can neither be removed nor appended to anymore.
+const FROZEN: u8 = 2;
+
+
 impl<Key, Val> EntryNode<Key, Val>
 where
     Key: Eq,
@@ -89,6 +165,35 @@ where
         &self.key
     }
 
+    /// Returns `true` if this node was removed from the map.
+    ///
+    /// Removed nodes are skipped by all lookups until they are unlinked from their chain.
+    #[inline]
+    pub fn is_removed(&self) -> bool
+    {
+        self.state.load(Ordering::SeqCst) & REMOVED != 0
+    }
+
+    /// Returns `true` if the bin of this node is being migrated, see `EntryNode::freeze`.
+    #[inline]
+    pub fn is_frozen(&self) -> bool
+    {
+        self.state.load(Ordering::SeqCst) & FROZEN != 0
+    }
+
+    /// Prevents any further removals from and appends to the chain starting at this node.
+    ///
+    /// Like `seal`, this fails appends that are already underway.
+    pub fn freeze(&self)
+    {
+        self.state.fetch_or(FROZEN, Ordering::SeqCst);
+
+        if let Some(next) = self.seal()
+        {
+            next.read().freeze();
+        }
+    }
+
     pub fn clone_striped(&self) -> Self
     {
         Self {
@@ -96,6 +201,7 @@ where
             key: self.key.clone(),
             hash: self.hash,
             next: Keep::new(None),
+            state: AtomicU8::new(0),
         }
     }
 
@@ -106,15 +212,39 @@ where
             key: Keep::new(key).read(),
             hash,
             next: Keep::new(None),
+            state: AtomicU8::new(0),
         }
     }
 
-    pub fn update(&self, node: &Keep<EntryNode<Key, Val>>) -> Option<Keep<Val>>
+    /// Replaces the value of the node with the key of `node`, or links in `node` if there is none.
+    ///
+    /// Chains are ordered by hash, so `node` is linked in after the last node whose hash is not greater
+    /// than its own. The chain has to start at such a node, see `Table::upsert_node`.
+    /// If `replace` is `false`, an existing value is returned instead of being replaced.
+    pub fn update(&self, node: &Keep<EntryNode<Key, Val>>, replace: bool) -> Update<Val>
     {
-        if self.key.as_ref() == node.read().key.as_ref()
+        if self.key.as_ref() == node.read().key.as_ref() && !self.is_removed()
         {
+            if !replace
+            {
+                return Update::Present(self.val.read());
+            }
+
+            // The copy of a frozen node would not see the new value.
+            if self.is_frozen()
+            {
+                return Update::Retry;
+            }
+
             let old = self.val.clone_from(&node.read().val);
-            return Some(old);
+
+            // If this node was removed or frozen in the meantime, the new value would be lost with it.
+            if self.state.load(Ordering::SeqCst) != 0
+            {
+                return Update::Retry;
+            }
+
+            return Update::Replaced(old);
         }
 
         let next = &self.next;
@@ -124,13 +254,31 @@ where
         {
             match &*next_guard
             {
-                Some(next) => return next.read().update(node),
+                // Nodes with the same key have the same hash, so they always come before the place of `node`.
+                Some(next) if next.read().hash <= node.read().hash =>
+                {
+                    return match next.read().update(node, replace)
+                    {
+                        Update::Appended(len) => Update::Appended(len + 1),
+                        update => update,
+                    };
+                }
 
-                None =>
+                successor =>
                 {
+                    // Never link a node behind a removed node, since it might already be unlinked,
+                    // or behind a frozen one, since it might already be copied.
+                    if self.state.load(Ordering::SeqCst) != 0
+                    {
+                        return Update::Retry;
+                    }
+
+                    // node is not linked in yet, so nobody else can observe its successor changing.
+                    node.read().next.write(successor.clone());
+
                     match next.exchange(&next_guard, Some(node.clone()))
                     {
-                        Ok(_old) => return None,
+                        Ok(_old) => return Update::Appended(1 + node.read().len()),
 
                         Err(actual) =>
                         {
@@ -142,27 +290,154 @@ where
         }
     }
 
-    pub fn search(&self, key: &Key) -> Option<Guard<Val>>
+    /// Marks the first live node containing `key` as removed and returns its value.
+    pub fn remove(&self, key: &Key) -> Option<Keep<Val>>
     {
+        // Neither removed nor frozen nodes can be removed.
         if &*self.key == key
+            && self
+                .state
+                .compare_exchange(0, REMOVED, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        {
+            return Some(self.val.clone());
+        }
+
+        match &*self.next.read()
+        {
+            Some(next) => next.read().remove(key),
+            None => None,
+        }
+    }
+
+    /// Prevents any further appends to this node and returns its successor.
+    ///
+    /// This replaces the `next` mutation with an identical one, so appends that started before
+    /// this node was marked as removed fail and observe the removal when retrying.
+    pub fn seal(&self) -> Option<Keep<EntryNode<Key, Val>>>
+    {
+        let mut next_guard = self.next.read();
+
+        loop
+        {
+            match self.next.exchange(&next_guard, (*next_guard).clone())
+            {
+                Ok(_old) => return (*next_guard).clone(),
+                Err(actual) => next_guard = actual,
+            }
+        }
+    }
+
+    /// Returns the value of the live node containing `key`, whose hash is `hash`.
+    ///
+    /// Since chains are ordered by hash, the search stops at the first node with a greater hash.
+    pub fn search(&self, key: &Key, hash: u64) -> Option<Guard<Val>>
+    {
+        if self.hash > hash
+        {
+            return None;
+        }
+
+        if &*self.key == key && !self.is_removed()
         {
             return Some(self.value().read());
         }
 
         match &*self.next.read()
         {
-            Some(next) => next.read().search(key),
+            Some(next) => next.read().search(key, hash),
+            None => None,
+        }
+    }
+
+    /// Returns the number of nodes in the chain starting at this node, removed ones included.
+    pub fn len(&self) -> usize
+    {
+        let mut len = 1;
+        let mut current = self.next.read();
+
+        while let Some(next) = &*current
+        {
+            len += 1;
+            current = next.read().next.read();
+        }
+
+        len
+    }
+
+    /// Returns the number of nodes preceding the live node containing `key`, removed ones included.
+    pub fn position(&self, key: &Key) -> Option<usize>
+    {
+        if &*self.key == key && !self.is_removed()
+        {
+            return Some(0);
+        }
+
+        match &*self.next.read()
+        {
+            Some(next) => next.read().position(key).map(|position| position + 1),
             None => None,
         }
     }
 
-    pub fn buffered(&self, buffer: &mut Vec<Guard<Val>>)
+    pub fn modify(&self, key: &Key, f: impl FnOnce(&Val) -> Val) -> bool
+    {
+        if &*self.key == key && !self.is_removed()
+        {
+            let new = f(&self.value().read());
+            self.value().write(new);
+            return true;
+        }
+
+        match &*self.next.read()
+        {
+            Some(next) => next.read().modify(key, f),
+            None => false,
+        }
+    }
+
+    pub fn buffered_pairs(
+        &self,
+        filter: &impl Fn(u64) -> bool,
+        buffer: &mut Vec<(Guard<Key>, Guard<Val>)>,
+    )
     {
-        buffer.push(self.value().read());
+        if !self.is_removed() && filter(self.hash)
+        {
+            buffer.push((self.key.clone(), self.value().read()));
+        }
 
         if let Some(next) = &*self.next.read()
         {
-            next.read().buffered(buffer);
+            next.read().buffered_pairs(filter, buffer);
+        }
+    }
+
+    /// Calls `f` with the key and value of this node and its successors, see `buffered_pairs`.
+    ///
+    /// The chain is walked iteratively, so only the guards to the current node, its link and
+    /// its value are held while `f` runs, however long the chain is.
+    pub fn for_each(&self, filter: &impl Fn(u64) -> bool, f: &mut impl FnMut(&Key, &Val))
+    {
+        self.visit(filter, f);
+
+        let mut current = self.next.read();
+
+        while let Some(next) = &*current
+        {
+            let node = next.read();
+            node.visit(filter, f);
+
+            current = node.next.read();
+        }
+    }
+
+    /// Calls `f` with the key and value of this node only, if it is live and its hash passes `filter`.
+    fn visit(&self, filter: &impl Fn(u64) -> bool, f: &mut impl FnMut(&Key, &Val))
+    {
+        if !self.is_removed() && filter(self.hash)
+        {
+            f(&self.key, &self.value().read());
         }
     }
 }
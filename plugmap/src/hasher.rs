@@ -0,0 +1,131 @@
+use core::hash::{BuildHasher, Hasher};
+
+#[cfg(feature = "std")]
+use std::hash::DefaultHasher;
+
+
+/// A deterministic `BuildHasher` seeded with a fixed value.
+///
+/// Maps using the same seed will always bucket keys identically, which makes
+/// collision dependent tests and bugs reproducible.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeededState
+{
+    seed: u64,
+}
+
+
+#[cfg(feature = "std")]
+impl SeededState
+{
+    pub fn new(seed: u64) -> Self
+    {
+        Self { seed }
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64
+    {
+        self.seed
+    }
+}
+
+
+#[cfg(feature = "std")]
+impl BuildHasher for SeededState
+{
+    type Hasher = DefaultHasher;
+
+    fn build_hasher(&self) -> Self::Hasher
+    {
+        // `DefaultHasher::new` always uses the same keys, so feeding the seed first
+        // yields a hasher that only depends on `seed`.
+        let mut hasher = DefaultHasher::new();
+        hasher.write_u64(self.seed);
+        hasher
+    }
+}
+
+
+/// A `BuildHasher` which uses the value a key writes as its hash, without mixing it any further.
+///
+/// This skips the cost of a full hash for keys which already are well distributed,
+/// like `TypeId`s or random integers. Keys whose values share their lowest bits,
+/// like multiples of a power of two, all end up in the same bin and should not use it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IdentityState;
+
+
+impl BuildHasher for IdentityState
+{
+    type Hasher = IdentityHasher;
+
+    fn build_hasher(&self) -> Self::Hasher
+    {
+        IdentityHasher(0)
+    }
+}
+
+
+/// The `Hasher` of `IdentityState`.
+///
+/// Integers are passed through, other bytes are folded into the hash.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct IdentityHasher(u64);
+
+
+impl Hasher for IdentityHasher
+{
+    #[inline]
+    fn finish(&self) -> u64
+    {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8])
+    {
+        for chunk in bytes.chunks(8)
+        {
+            let mut buf = [0; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+
+            self.0 = (self.0.rotate_left(5) ^ u64::from_ne_bytes(buf)).wrapping_mul(FOLD);
+        }
+    }
+
+    #[inline]
+    fn write_u8(&mut self, i: u8)
+    {
+        self.write_u64(i as u64);
+    }
+
+    #[inline]
+    fn write_u16(&mut self, i: u16)
+    {
+        self.write_u64(i as u64);
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32)
+    {
+        self.write_u64(i as u64);
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64)
+    {
+        // Keys writing multiple integers still depend on all of them.
+        self.0 = self.0.rotate_left(5) ^ i;
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize)
+    {
+        self.write_u64(i as u64);
+    }
+}
+
+
+/// An odd constant with well mixed bits, used to fold bytes into the hash.
+const FOLD: u64 = 0x517c_c1b7_2722_0a95;
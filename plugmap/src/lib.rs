@@ -1,13 +1,41 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![allow(unused)]
 
 
+extern crate alloc;
+
+
+mod bin_lock;
+mod bytes;
 mod entry;
+mod hasher;
 mod map;
 mod resizer;
+mod small;
 mod table;
 
 
-pub use map::PlugMap;
+pub use bytes::GuardedSlice;
+pub use hasher::{IdentityHasher, IdentityState};
+pub use map::{BinStats, PlugMap};
+
+#[cfg(feature = "std")]
+pub use hasher::SeededState;
+
+#[cfg(feature = "test-hooks")]
+pub use resizer::ResizeObserver;
+
+
+/// Lets other threads make progress, by yielding to the scheduler or by spinning without `std`.
+#[inline]
+pub(crate) fn yield_now()
+{
+    #[cfg(feature = "std")]
+    std::thread::yield_now();
+
+    #[cfg(not(feature = "std"))]
+    core::hint::spin_loop();
+}
 
 
 #[cfg(test)]
@@ -16,6 +44,7 @@ mod tests
     use std::thread;
 
     use super::*;
+    use keep::Keep;
 
     #[test]
     fn look_and_feel()
@@ -44,22 +73,354 @@ mod tests
         );
     }
 
-    // #[test]
-    // fn remove()
-    // {
-    //     let map = PlugMap::<u32, &str>::new();
+    #[test]
+    fn get_slice()
+    {
+        let map = PlugMap::<&str, Vec<u8>>::new();
+
+        assert!(map.get_slice(&"blob").is_none());
+        map.insert("blob", vec![3, 9, 39]);
 
-    //     assert!(map.remove(&39).is_none());
-    //     map.insert(39, "Briar");
-    //     assert_eq!(Some("Briar"), map.remove(&39).map(|g| *g.read()));
-    //     assert!(map.remove(&39).is_none());
-    //     assert!(map.insert(39, "Other").is_none());
-    //     assert_eq!(Some("Other"), map.remove(&39).map(|g| *g.read()));
-    //     assert!(map.remove(&39).is_none());
-    // }
+        let slice = map.get_slice(&"blob").unwrap();
+        assert_eq!(&[3, 9, 39], &*slice);
+        assert_eq!(3, slice.len());
+    }
 
     #[test]
-    fn many_entries()
+    fn seeded()
+    {
+        let a = PlugMap::<usize, usize, _>::with_seed(4, 39);
+        let b = PlugMap::<usize, usize, _>::with_seed(4, 39);
+
+        for key in 0..100
+        {
+            assert_eq!(a.bin_of(&key), b.bin_of(&key));
+        }
+
+        a.insert(3, 9);
+        assert_eq!(Some(9), a.get(&3).map(|v| *v));
+    }
+
+    #[test]
+    fn clone_custom_hasher()
+    {
+        // Neither `Clone` nor `Default`, clones share the one instance.
+        struct Fixed(u64);
+
+        impl std::hash::BuildHasher for Fixed
+        {
+            type Hasher = IdentityHasher;
+
+            fn build_hasher(&self) -> IdentityHasher
+            {
+                IdentityState.build_hasher()
+            }
+        }
+
+        let map = PlugMap::new_with_hasher(4, Fixed(39));
+        let clone = map.clone();
+
+        let writer = thread::spawn(move || {
+            for key in 0..100
+            {
+                clone.insert(key, key * 2);
+            }
+        });
+
+        writer.join().unwrap();
+        assert!((0..100).all(|key| map.get(&key).is_some_and(|val| *val == key * 2)));
+    }
+
+    #[test]
+    fn identity_hasher()
+    {
+        use std::{any::TypeId, collections::HashSet, hash::BuildHasher};
+
+        fn ids() -> [TypeId; 8]
+        {
+            [
+                TypeId::of::<u8>(),
+                TypeId::of::<u16>(),
+                TypeId::of::<u32>(),
+                TypeId::of::<u64>(),
+                TypeId::of::<i8>(),
+                TypeId::of::<String>(),
+                TypeId::of::<Vec<u8>>(),
+                TypeId::of::<Option<usize>>(),
+            ]
+        }
+
+        // Integers are their own hashes.
+        assert_eq!(0xdead_beef, IdentityState.hash_one(0xdead_beef_u64));
+        assert_eq!(39, IdentityState.hash_one(39_u32));
+        assert_eq!(7, IdentityState.hash_one(7_usize));
+
+        // Different type ids keep different hashes.
+        let hashes: HashSet<_> = ids().iter().map(|id| IdentityState.hash_one(id)).collect();
+        assert_eq!(ids().len(), hashes.len());
+
+        let map = PlugMap::new_with_identity_hasher();
+
+        for (i, id) in ids().into_iter().enumerate()
+        {
+            map.insert(id, i);
+        }
+
+        for (i, id) in ids().iter().enumerate()
+        {
+            assert_eq!(Some(i), map.get(id).map(|v| *v));
+        }
+
+        assert!(map.get(&TypeId::of::<i64>()).is_none());
+    }
+
+    #[test]
+    fn rehash()
+    {
+        let map = PlugMap::new_with_identity_hasher();
+
+        // With the identity hasher, all of these keys end up in the first bin.
+        for i in 0..10
+        {
+            map.insert(i << 16, i);
+        }
+
+        assert!((0..10).all(|i| map.bin_of(&(i << 16)) == 0));
+
+        let kept = map.get(&(3 << 16)).unwrap().into_keep();
+        map.rehash();
+
+        let mut bins: Vec<_> = (0..10).map(|i| map.bin_of(&(i << 16))).collect();
+        bins.sort();
+        bins.dedup();
+
+        assert!(bins.len() > 1);
+        assert_eq!(10, map.iter().count());
+        assert!((0..10).all(|i| map.get(&(i << 16)).is_some_and(|val| *val == i)));
+
+        // Values are shared with the entries before the rehash.
+        kept.write(39);
+        assert_eq!(Some(39), map.get(&(3 << 16)).map(|val| *val));
+    }
+
+    #[test]
+    fn rehash_while_writing()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..1000
+        {
+            map.insert(key, key);
+        }
+
+        let iter = map.iter();
+        let writer = {
+            let map = map.clone();
+
+            thread::spawn(move || {
+                for key in 1000..3000
+                {
+                    map.insert(key, key);
+                }
+            })
+        };
+
+        while !writer.is_finished()
+        {
+            map.rehash();
+        }
+
+        writer.join().unwrap();
+        map.rehash();
+
+        assert_eq!(3000, map.entry_count());
+        assert!((0..3000).all(|key| map.get(&key).is_some_and(|val| *val == key)));
+
+        // The iterator started before the rehashes still walks the bins it started with.
+        let mut seen: Vec<_> = iter
+            .map(|(key, _)| *key)
+            .filter(|key| *key < 1000)
+            .collect();
+        seen.sort();
+        seen.dedup();
+        assert_eq!((0..1000).collect::<Vec<_>>(), seen);
+    }
+
+    #[test]
+    fn keep_keys()
+    {
+        let map = PlugMap::new();
+
+        map.insert(Keep::new(String::from("Briar")), 39);
+        map.insert(Keep::new(String::from("Miku")), 3);
+
+        assert_eq!(
+            Some(39),
+            map.get(&Keep::new(String::from("Briar"))).map(|v| *v)
+        );
+        assert_eq!(
+            Some(3),
+            map.get(&Keep::new(String::from("Miku"))).map(|v| *v)
+        );
+        assert!(map.get(&Keep::new(String::from("Gwen"))).is_none());
+    }
+
+    #[test]
+    fn remove()
+    {
+        let map = PlugMap::<u32, &str>::new();
+
+        assert!(map.remove(&39).is_none());
+        map.insert(39, "Briar");
+        assert_eq!(Some("Briar"), map.remove(&39).map(|g| *g.read()));
+        assert!(map.remove(&39).is_none());
+        assert!(map.insert(39, "Other").is_none());
+        assert_eq!(Some("Other"), map.remove(&39).map(|g| *g.read()));
+        assert!(map.remove(&39).is_none());
+    }
+
+    #[test]
+    fn remove_value()
+    {
+        let map = PlugMap::new();
+
+        map.insert(39, String::from("Briar"));
+        map.insert(3, String::from("Miku"));
+
+        assert_eq!(Some(String::from("Briar")), map.remove_value(&39));
+        assert!(map.remove_value(&39).is_none());
+
+        // Values still referred to elsewhere are cloned.
+        let guard = map.get(&3).unwrap();
+        assert_eq!(Some(String::from("Miku")), map.remove_value(&3));
+        assert_eq!("Miku", *guard);
+    }
+
+    #[test]
+    fn count_exact()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..500
+        {
+            map.insert(key, key);
+        }
+
+        for key in (0..500).step_by(3)
+        {
+            map.remove(&key);
+        }
+
+        // Removed keys are added again, present ones only have their values replaced.
+        for key in 0..100
+        {
+            map.insert(key, key + 1);
+        }
+
+        assert_eq!(367, map.count_exact());
+        assert_eq!(map.entry_count(), map.count_exact());
+    }
+
+    #[test]
+    fn bin_stats()
+    {
+        // All keys hash to the same bin.
+        #[derive(PartialEq, Eq)]
+        struct Colliding(usize);
+
+        impl std::hash::Hash for Colliding
+        {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H)
+            {
+                state.write_u8(39);
+            }
+        }
+
+        let map = PlugMap::new();
+        assert_eq!(0, map.bin_stats().longest_chain);
+
+        for key in 0..50
+        {
+            map.insert(Colliding(key), key);
+        }
+
+        let stats = map.bin_stats();
+        assert_eq!(50, stats.longest_chain);
+        assert_eq!(1, stats.occupied_bins);
+        assert_eq!(map.capacity() - 1, stats.empty_bins);
+    }
+
+    #[test]
+    fn double_remove()
+    {
+        let map = PlugMap::new();
+
+        for _ in 0..200
+        {
+            let clone = map.clone();
+
+            map.insert(39, "Briar");
+            map.insert(3, "Miku");
+
+            let t = thread::spawn(move || clone.remove(&39).is_some());
+            let removed = map.remove(&39).is_some();
+
+            // Exactly one of the removes succeeds and decrements the count.
+            assert!(removed ^ t.join().unwrap());
+            assert!(map.remove(&39).is_none());
+            assert_eq!(1, map.entry_count());
+
+            // A second decrement for the same entry would underflow here and trip the debug assertion.
+            map.remove(&3);
+            assert!(map.remove(&3).is_none());
+            assert_eq!(0, map.entry_count());
+        }
+    }
+
+    #[test]
+    fn get_or_try_insert_with()
+    {
+        let map = PlugMap::<&str, usize>::new();
+
+        assert_eq!(
+            Err("offline"),
+            map.get_or_try_insert_with("Briar", || Err("offline"))
+                .map(|v| *v)
+        );
+        assert!(map.get(&"Briar").is_none());
+
+        assert_eq!(
+            Ok(39),
+            map.get_or_try_insert_with("Briar", || Ok::<_, ()>(39))
+                .map(|v| *v)
+        );
+        assert_eq!(
+            Ok(39),
+            map.get_or_try_insert_with("Briar", || -> Result<_, ()> { unreachable!() })
+                .map(|v| *v)
+        );
+    }
+
+    #[test]
+    fn drain()
+    {
+        let map = PlugMap::new();
+
+        for i in 0..100
+        {
+            map.insert(i, i * 2);
+        }
+
+        let mut drained: Vec<_> = map.drain().map(|(k, v)| (*k, *v.read())).collect();
+        drained.sort();
+
+        assert_eq!((0..100).map(|i| (i, i * 2)).collect::<Vec<_>>(), drained);
+        assert!(map.get(&39).is_none());
+        assert_eq!(0, map.iter().count());
+    }
+
+    #[test]
+    fn into_values()
     {
         let map = PlugMap::new();
 
@@ -68,13 +429,828 @@ mod tests
             map.insert(i, i.to_string());
         }
 
-        assert_eq!(Some("39"), map.get(&39).as_ref().map(|g| g.as_str()));
-        // assert_eq!(
-        //     Some("39"),
-        //     map.remove(&39).map(|k| k.read().to_string()).as_deref()
-        // );
-        // assert!(map.remove(&39).is_none());
-        // assert_eq!(None, map.get(&39));
+        let mut values: Vec<_> = map.into_values().collect();
+        values.sort_by_key(|v| v.parse::<usize>().unwrap());
+
+        assert_eq!((0..100).map(|i| i.to_string()).collect::<Vec<_>>(), values);
+    }
+
+    #[test]
+    fn bin_lock()
+    {
+        // All keys hash to the same bin.
+        #[derive(PartialEq, Eq)]
+        struct Colliding(usize);
+
+        impl std::hash::Hash for Colliding
+        {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H)
+            {
+                state.write_u8(39);
+            }
+        }
+
+        use entry::EntryNode;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use table::Table;
+
+        let table = Table::<usize, usize>::new_with_bin_lock(4, 8);
+
+        // All nodes share a hash, so they end up in the chain of the first bin.
+        for key in 0..8
+        {
+            table.insert(EntryNode::new(key, key, 0));
+        }
+
+        // Up to the threshold, writers don't lock the bin.
+        assert!(table.lock_bin(0).is_none());
+
+        table.insert(EntryNode::new(8, 8, 0));
+        let lock = table
+            .lock_bin(0)
+            .expect("the chain is longer than the threshold");
+
+        // Other bins stay lock-free while the long one is locked.
+        assert!(table.lock_bin(1).is_none());
+        table.insert(EntryNode::new(1, 1, 1));
+
+        // Writers of the long bin wait until the lock is released.
+        let inserted = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            let writer = scope.spawn(|| {
+                table.insert(EntryNode::new(9, 9, 0));
+                inserted.store(true, Ordering::SeqCst);
+            });
+
+            for _ in 0..100
+            {
+                thread::yield_now();
+            }
+
+            assert!(!inserted.load(Ordering::SeqCst));
+            assert!(table.get(&9, 0).is_none());
+
+            drop(lock);
+            writer.join().unwrap();
+        });
+
+        assert_eq!(Some(9), table.get(&9, 0).map(|val| *val));
+
+        let map = PlugMap::new_with_bin_lock(8);
+        let mut threads = vec![];
+
+        for t in 0..4
+        {
+            let map = map.clone();
+            threads.push(thread::spawn(move || {
+                for i in 0..100
+                {
+                    map.insert(Colliding(t * 100 + i), t * 100 + i);
+                }
+
+                for i in (0..100).step_by(2)
+                {
+                    assert_eq!(
+                        Some(t * 100 + i),
+                        map.remove(&Colliding(t * 100 + i)).map(|v| *v.read())
+                    );
+                }
+            }));
+        }
+
+        for t in threads
+        {
+            t.join().unwrap();
+        }
+
+        for key in 0..400
+        {
+            let expected = (key % 2 == 1).then_some(key);
+            assert_eq!(expected, map.get(&Colliding(key)).map(|v| *v));
+        }
+    }
+
+    #[test]
+    fn max_chain()
+    {
+        use entry::EntryNode;
+        use table::Table;
+
+        let table = Table::<usize, usize>::new_with_max_chain(4, 8);
+
+        // All nodes share a hash, so they end up in the same chain.
+        for key in 0..8
+        {
+            assert!(!table.insert(EntryNode::new(key, key, 39)).1);
+        }
+
+        assert!(table.insert(EntryNode::new(8, 8, 39)).1);
+
+        // Replacing a value does not grow the chain.
+        assert!(!table.insert(EntryNode::new(3, 9, 39)).1);
+    }
+
+    #[test]
+    fn chain_len()
+    {
+        use entry::EntryNode;
+        use table::Table;
+
+        let table = Table::<usize, usize>::new(4);
+
+        // All nodes share a hash, so they end up in the same chain in insertion order.
+        for key in 0..4
+        {
+            table.insert(EntryNode::new(key, key, 39));
+        }
+
+        for key in 0..4
+        {
+            assert_eq!(Some(key), table.chain_len(&key, 39));
+        }
+
+        assert!(table.chain_len(&4, 39).is_none());
+
+        // Removed nodes are unlinked, so the following nodes move up.
+        table.remove(&1, 39);
+        assert!(table.chain_len(&1, 39).is_none());
+        assert_eq!(Some(2), table.chain_len(&3, 39));
+    }
+
+    #[test]
+    fn chain_ordered_by_hash()
+    {
+        use entry::EntryNode;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use table::Table;
+
+        static EQ_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        // Counts how many nodes a search compares its key against.
+        #[derive(Eq)]
+        struct Key(u64);
+
+        impl std::hash::Hash for Key
+        {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H)
+            {
+                self.0.hash(state);
+            }
+        }
+
+        impl PartialEq for Key
+        {
+            fn eq(&self, other: &Self) -> bool
+            {
+                EQ_CALLS.fetch_add(1, Ordering::SeqCst);
+                self.0 == other.0
+            }
+        }
+
+        let table = Table::<Key, u64>::new(4);
+
+        // All of these hashes land in the first bin, but are inserted out of order.
+        for hash in [48, 16, 64, 32]
+        {
+            table.insert(EntryNode::new(Key(hash), hash, hash));
+        }
+
+        for (position, hash) in [16, 32, 48, 64].into_iter().enumerate()
+        {
+            assert_eq!(Some(position), table.chain_len(&Key(hash), hash));
+            assert_eq!(Some(hash), table.get(&Key(hash), hash).map(|val| *val));
+        }
+
+        // Prepending and replacing keep the order.
+        table.insert(EntryNode::new(Key(0), 0, 0));
+        table.insert(EntryNode::new(Key(32), 39, 32));
+
+        assert_eq!(Some(0), table.chain_len(&Key(0), 0));
+        assert_eq!(Some(2), table.chain_len(&Key(32), 32));
+        assert_eq!(Some(39), table.get(&Key(32), 32).map(|val| *val));
+
+        // A missing key of hash 32 stops at the first greater hash, after comparing 0, 16 and 32 but not 48 and 64.
+        EQ_CALLS.store(0, Ordering::SeqCst);
+        assert!(table.get(&Key(33), 32).is_none());
+        assert_eq!(3, EQ_CALLS.load(Ordering::SeqCst));
+
+        // A miss past the end of the chain compares every node.
+        EQ_CALLS.store(0, Ordering::SeqCst);
+        assert!(table.get(&Key(80), 80).is_none());
+        assert_eq!(5, EQ_CALLS.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn iter_sorted()
+    {
+        let map = PlugMap::new();
+
+        // 37 is coprime to 101, so this inserts every key in 0..101 exactly once, shuffled.
+        for i in 0..101
+        {
+            let key = i * 37 % 101;
+            map.insert(key, key * 2);
+        }
+
+        let sorted = map.iter_sorted();
+        assert_eq!(101, sorted.len());
+
+        for (i, (key, val)) in sorted.iter().enumerate()
+        {
+            assert_eq!(i, **key);
+            assert_eq!(i * 2, **val);
+        }
+    }
+
+    #[test]
+    fn keys_and_values_vec()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..100
+        {
+            map.insert(key, key * 2);
+        }
+
+        let mut keys: Vec<_> = map.keys_vec().iter().map(|key| **key).collect();
+        let mut values: Vec<_> = map.values_vec().iter().map(|val| **val).collect();
+
+        keys.sort();
+        values.sort();
+
+        assert_eq!((0..100).collect::<Vec<_>>(), keys);
+        assert_eq!((0..100).map(|key| key * 2).collect::<Vec<_>>(), values);
+        assert!(PlugMap::<usize, usize>::new().keys_vec().is_empty());
+    }
+
+    #[test]
+    fn for_each()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..1000
+        {
+            map.insert(key, key * 2);
+        }
+
+        let values: Vec<_> = map.iter_keeps().collect();
+
+        let mut sum = 0;
+        let mut visited = 0;
+        map.for_each(|key, val| {
+            assert_eq!(key * 2, *val);
+            sum += val;
+            visited += 1;
+        });
+
+        assert_eq!(1000, visited);
+        assert_eq!(map.iter().map(|(_, val)| *val).sum::<i32>(), sum);
+
+        // No guard outlives the callback it was read for, neither to a value nor to the chain.
+        for val in values
+        {
+            val.assert_no_outstanding_guards();
+        }
+
+        assert_no_chain_guards(&map);
+    }
+
+    /// Panics if a guard to the table, a bin or a node of a chain of `map` is still alive.
+    fn assert_no_chain_guards(map: &PlugMap<i32, i32>)
+    {
+        use entry::Entry;
+
+        map.table().assert_no_outstanding_guards();
+        let table = map.table().read();
+
+        for entry in table.entries()
+        {
+            entry.assert_no_outstanding_guards();
+
+            let Entry::Head(head) = &*entry.read()
+            else
+            {
+                continue;
+            };
+
+            head.assert_no_outstanding_guards();
+            let mut node = head.read();
+
+            loop
+            {
+                node.next().assert_no_outstanding_guards();
+
+                let Some(next) = (*node.next().read()).clone()
+                else
+                {
+                    break;
+                };
+
+                next.assert_no_outstanding_guards();
+                node = next.read();
+            }
+        }
+    }
+
+    #[test]
+    fn keys_and_values_cloned()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..100
+        {
+            map.insert(key, key.to_string());
+        }
+
+        let collected: Vec<String> = (&map).into_iter().map(|val| (*val).clone()).collect();
+        assert_eq!(collected, map.values_cloned());
+
+        let mut keys = map.keys_cloned();
+        keys.sort();
+        assert_eq!((0..100).collect::<Vec<_>>(), keys);
+    }
+
+    #[test]
+    fn entry_count_while_resizing()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..1000
+        {
+            map.insert(key, key);
+        }
+
+        let resizer = {
+            let map = map.clone();
+
+            thread::spawn(move || {
+                for _ in 0..4
+                {
+                    map.resize();
+                }
+            })
+        };
+
+        // Entries being migrated are counted by either the old or the new table, never by both.
+        while !resizer.is_finished()
+        {
+            assert!(map.entry_count() <= 1000);
+        }
+
+        resizer.join().unwrap();
+        assert_eq!(1000, map.entry_count());
+    }
+
+    #[test]
+    fn insert_grows()
+    {
+        let map = PlugMap::new();
+        let capacity = map.capacity();
+
+        for key in 0..capacity * 4
+        {
+            map.insert(key, key);
+        }
+
+        assert!(map.capacity() >= capacity * 4);
+
+        let map = PlugMap::new();
+
+        for key in 0..capacity * 4
+        {
+            assert_eq!(
+                Ok(key),
+                map.get_or_try_insert_with(key, || Ok::<_, ()>(key))
+                    .map(|val| *val)
+            );
+        }
+
+        assert!(map.capacity() >= capacity * 4);
+        assert!((0..capacity * 4).all(|key| map.get(&key).is_some_and(|val| *val == key)));
+    }
+
+    #[test]
+    fn small()
+    {
+        let map = PlugMap::new_small();
+
+        for key in 0..3
+        {
+            map.insert(key, key * 2);
+        }
+
+        // All entries share the single bin, which is scanned linearly.
+        assert_eq!(1, map.capacity());
+        assert_eq!(
+            BinStats {
+                occupied_bins: 1,
+                empty_bins: 0,
+                longest_chain: 3,
+            },
+            map.bin_stats()
+        );
+        assert_eq!(Some(4), map.get(&2).map(|val| *val));
+
+        let kept = map.remove(&2).unwrap();
+        map.insert(2, 4);
+
+        for key in 3..PlugMap::<usize, usize>::SMALL_LIMIT
+        {
+            map.insert(key, key * 2);
+        }
+
+        // The map is only promoted once it holds more than `SMALL_LIMIT` entries.
+        assert_eq!(1, map.capacity());
+        map.insert(PlugMap::<usize, usize>::SMALL_LIMIT, 16);
+
+        assert!(map.capacity() >= 16);
+        assert!(map.bin_stats().occupied_bins > 1);
+        assert_eq!(PlugMap::<usize, usize>::SMALL_LIMIT + 1, map.entry_count());
+
+        for key in 0..=PlugMap::<usize, usize>::SMALL_LIMIT
+        {
+            assert_eq!(Some(key * 2), map.get(&key).map(|val| *val));
+        }
+
+        assert_eq!(4, *kept.read());
+    }
+
+    #[test]
+    fn small_concurrent_promotion()
+    {
+        let map = PlugMap::new_small();
+        let mut threads = vec![];
+
+        for t in 0..4
+        {
+            let map = map.clone();
+
+            threads.push(thread::spawn(move || {
+                for key in t * 50..(t + 1) * 50
+                {
+                    map.insert(key, key);
+                }
+            }));
+        }
+
+        for t in threads
+        {
+            t.join().unwrap();
+        }
+
+        assert_eq!(200, map.entry_count());
+        assert!((0..200).all(|key| map.get(&key).is_some_and(|val| *val == key)));
+    }
+
+    #[test]
+    fn modify()
+    {
+        let map = PlugMap::new();
+        map.insert("Briar", 39);
+
+        let old = map.get(&"Briar").unwrap();
+
+        assert!(map.modify(&"Briar", |val| val + 3));
+        assert!(!map.modify(&"Miku", |val| val + 3));
+
+        assert_eq!(42, *map.get(&"Briar").unwrap());
+        assert_eq!(39, *old);
+        assert!(map.get(&"Miku").is_none());
+    }
+
+    #[test]
+    fn resize_while_reading()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..1000
+        {
+            map.insert(key, key);
+        }
+
+        let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut readers = vec![];
+
+        for _ in 0..4
+        {
+            let map = map.clone();
+            let done = done.clone();
+
+            readers.push(thread::spawn(move || {
+                while !done.load(std::sync::atomic::Ordering::SeqCst)
+                {
+                    for key in (0..1000).step_by(37)
+                    {
+                        assert_eq!(Some(key), map.get(&key).map(|val| *val));
+                    }
+                }
+            }));
+        }
+
+        let writer = {
+            let map = map.clone();
+
+            thread::spawn(move || {
+                for key in 1000..3000
+                {
+                    map.insert(key, key);
+                }
+            })
+        };
+
+        let capacity = map.capacity();
+        for _ in 0..4
+        {
+            map.resize();
+        }
+
+        writer.join().unwrap();
+        done.store(true, std::sync::atomic::Ordering::SeqCst);
+
+        for reader in readers
+        {
+            reader.join().unwrap();
+        }
+
+        // Inserting might have resized the map on its own as well.
+        assert!(map.capacity() >= capacity << 4);
+        assert_eq!(3000, map.iter().count());
+        assert!((0..3000).all(|key| map.get(&key).is_some_and(|val| *val == key)));
+    }
+
+    #[test]
+    fn scan_while_resizing()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..1000
+        {
+            map.insert(key, key);
+        }
+
+        let writer = {
+            let map = map.clone();
+
+            thread::spawn(move || {
+                for key in 1000..3000
+                {
+                    map.insert(key, key);
+                }
+
+                for _ in 0..4
+                {
+                    map.resize();
+                }
+            })
+        };
+
+        while !writer.is_finished()
+        {
+            let mut seen = std::collections::HashSet::new();
+
+            map.scan(|key, val| {
+                assert_eq!(key, val);
+                seen.insert(*key);
+            });
+
+            // Entries present for the whole scan are never skipped.
+            assert!((0..1000).all(|key| seen.contains(&key)));
+            assert!(seen.iter().all(|key| *key < 3000));
+        }
+
+        writer.join().unwrap();
+
+        let mut count = 0;
+        map.scan(|_, _| count += 1);
+        assert_eq!(3000, count);
+    }
+
+    #[cfg(feature = "test-hooks")]
+    #[test]
+    fn drain_during_swap()
+    {
+        use std::sync::{
+            Mutex,
+            mpsc::{Receiver, Sender, channel},
+        };
+
+        #[derive(Debug, PartialEq)]
+        enum Step
+        {
+            Entered,
+            Claimed(usize),
+            Swapped,
+        }
+
+        // Reports each step and blocks the resize until the test lets it continue.
+        struct Stepper
+        {
+            steps: Mutex<Sender<Step>>,
+            resume: Mutex<Receiver<()>>,
+        }
+
+        impl Stepper
+        {
+            fn step(&self, step: Step)
+            {
+                self.steps.lock().unwrap().send(step).unwrap();
+                self.resume.lock().unwrap().recv().unwrap();
+            }
+        }
+
+        impl ResizeObserver for Stepper
+        {
+            fn worker_entered(&self)
+            {
+                self.step(Step::Entered);
+            }
+
+            fn stride_claimed(&self, start: usize, _end: usize)
+            {
+                self.step(Step::Claimed(start));
+            }
+
+            fn swap_performed(&self)
+            {
+                self.step(Step::Swapped);
+            }
+        }
+
+        // Twelve entries fill the 16 bins just up to the load factor, so inserting them doesn't resize.
+        let map = PlugMap::new_with_hasher(4, IdentityState);
+
+        for key in 0..12
+        {
+            map.insert(key, key);
+        }
+
+        let (step_sender, steps) = channel();
+        let (resume, resume_receiver) = channel();
+
+        let resizer = {
+            let map = map.clone();
+
+            thread::spawn(move || {
+                map.resize_observed(&Stepper {
+                    steps: Mutex::new(step_sender),
+                    resume: Mutex::new(resume_receiver),
+                })
+            })
+        };
+
+        // Nothing was migrated yet, so all reads fall back to the old table.
+        assert_eq!(Step::Entered, steps.recv().unwrap());
+        assert_eq!(32, map.capacity());
+        let early = map.get(&9).unwrap();
+        resume.send(()).unwrap();
+
+        // The identity hasher puts each key into bin `key % 16` of the old table.
+        assert_eq!(Step::Claimed(0), steps.recv().unwrap());
+        resume.send(()).unwrap();
+        assert_eq!(Step::Claimed(8), steps.recv().unwrap());
+
+        // Start iterating while one stride is migrated and the other is not.
+        let mut iter = map.iter();
+        let mut seen: Vec<_> = iter.by_ref().take(4).map(|(key, _)| *key).collect();
+        resume.send(()).unwrap();
+
+        // Claims past the end of the old table are not reported, all bins are migrated before the swap.
+        assert_eq!(Step::Swapped, steps.recv().unwrap());
+
+        // The iterator started before the swap keeps draining the bigger table after it.
+        seen.extend(iter.map(|(key, _)| *key));
+        resume.send(()).unwrap();
+        resizer.join().unwrap();
+
+        seen.sort();
+        assert_eq!((0..12).collect::<Vec<_>>(), seen);
+        assert_eq!(9, *early);
+        assert!((0..12).all(|key| map.get(&key).is_some_and(|val| *val == key)));
+    }
+
+    #[test]
+    fn replace_all()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..100
+        {
+            map.insert(key, 0);
+        }
+
+        let old = map.get(&39).unwrap();
+        let reader = {
+            let map = map.clone();
+
+            thread::spawn(move || {
+                for _ in 0..100
+                {
+                    let values: Vec<_> = map.iter().map(|(_, val)| *val).collect();
+                    assert!(values.iter().all(|val| *val == values[0]));
+                }
+            })
+        };
+
+        for generation in 1..=10
+        {
+            map.replace_all((0..100).map(|key| (key, generation)));
+        }
+
+        reader.join().unwrap();
+
+        assert_eq!(0, *old);
+        assert_eq!(Some(10), map.get(&39).map(|v| *v));
+        assert_eq!(100, map.iter().count());
+
+        map.replace_all([(3, 9)]);
+        assert!(map.get(&39).is_none());
+        assert_eq!(Some(9), map.get(&3).map(|v| *v));
+
+        // Replacing waits for resizes running concurrently instead of swapping out a table mid-migration.
+        let resizer = {
+            let map = map.clone();
+
+            thread::spawn(move || {
+                for _ in 0..20
+                {
+                    map.resize();
+                }
+            })
+        };
+
+        for generation in 0..20
+        {
+            map.replace_all((0..100).map(|key| (key, generation)));
+        }
+
+        resizer.join().unwrap();
+
+        assert_eq!(100, map.entry_count());
+        assert!((0..100).all(|key| map.get(&key).is_some_and(|val| *val == 19)));
+
+        // Replacing the entries of a small map claims its promotion.
+        let small = PlugMap::new_small();
+        small.insert(3, 3);
+        small.replace_all((0..20).map(|key| (key, key)));
+        small.insert(39, 39);
+
+        assert_eq!(21, small.entry_count());
+        assert_eq!(Some(39), small.get(&39).map(|v| *v));
+    }
+
+    #[test]
+    fn guard_into_keep()
+    {
+        let map = PlugMap::new();
+        map.insert("Briar", 39);
+
+        let (_, val) = map.iter().next().unwrap();
+        val.into_keep().write(3);
+
+        assert_eq!(Some(3), map.get(&"Briar").map(|v| *v));
+    }
+
+    #[test]
+    fn iter_keeps()
+    {
+        let map = PlugMap::new();
+
+        for key in 0..10
+        {
+            map.insert(key, key);
+        }
+
+        let keeps: Vec<_> = map.iter_keeps().collect();
+        assert_eq!(10, keeps.len());
+
+        let keep = keeps.iter().find(|keep| *keep.read() == 3).unwrap();
+        keep.write(39);
+        assert_eq!(Some(39), map.get(&3).map(|v| *v));
+
+        // Inserting replaces the value cell, which detaches the handle.
+        map.insert(3, 9);
+        keep.write(3);
+        assert_eq!(Some(9), map.get(&3).map(|v| *v));
+    }
+
+    #[test]
+    fn many_entries()
+    {
+        let map = PlugMap::new();
+
+        for i in 0..100
+        {
+            map.insert(i, i.to_string());
+        }
+
+        assert_eq!(Some("39"), map.get(&39).as_ref().map(|g| g.as_str()));
+        assert_eq!(
+            Some("39"),
+            map.remove(&39).map(|k| k.read().to_string()).as_deref()
+        );
+        assert!(map.remove(&39).is_none());
+        assert_eq!(None, map.get(&39));
         assert_eq!(Some("31"), map.get(&31).as_ref().map(|g| g.as_str()));
     }
 
@@ -1,21 +1,60 @@
 use crate::{
+    bytes::GuardedSlice,
     entry::EntryNode,
-    table::{Table, TableIter},
+    hasher::IdentityState,
+    resizer::{ResizeObserver, Resizer},
+    small::{Small, SmallWriter},
+    table::{Table, TableIter, TablePairIter},
 };
+use alloc::{vec, vec::Vec};
+use core::hash::{BuildHasher, Hash};
 use keep::*;
-use std::hash::{BuildHasher, Hash, RandomState};
 
+#[cfg(feature = "std")]
+use crate::hasher::SeededState;
+#[cfg(feature = "std")]
+use std::hash::RandomState;
 
-pub struct PlugMap<Key, Val, S = RandomState>
+
+/// The hasher of maps which do not name one. Without `std` there is no source of random keys,
+/// so such maps have to be created with a hasher, like `new_with_identity_hasher`.
+#[cfg(feature = "std")]
+type DefaultState = RandomState;
+#[cfg(not(feature = "std"))]
+type DefaultState = IdentityState;
+
+
+/// How the entries of a map are distributed over its bins, see `PlugMap::bin_stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BinStats
+{
+    /// The number of bins holding at least one entry.
+    pub occupied_bins: usize,
+
+    /// The number of bins holding no entries.
+    pub empty_bins: usize,
+
+    /// The number of entries in the fullest bin.
+    pub longest_chain: usize,
+}
+
+
+pub struct PlugMap<Key, Val, S = DefaultState>
 {
     table: Keep<Table<Key, Val>>,
     hasher: Guard<S>,
+
+    // Only set for maps created by `new_small`.
+    small: Option<Guard<Small>>,
 }
 
 
 impl<Key, Val, S> PlugMap<Key, Val, S>
 {
     pub const DEFAULT_SIZE: usize = 4;
+
+    /// The number of entries a map created by `new_small` holds before it is promoted to a hashed table.
+    pub const SMALL_LIMIT: usize = 8;
 }
 
 
@@ -29,14 +68,106 @@ where
     {
         Self {
             table: Keep::new(Table::new(size)),
-            hasher: Keep::new(hasher).read(),
+            hasher: Keep::new_immutable(hasher).read(),
+            small: None,
         }
     }
 
+    /// Creates a new PlugMap like `new_with_hasher`, which additionally locks bins
+    /// whose collision chain is longer than `threshold` during inserts and removals.
+    ///
+    /// This trades lock-freedom for throughput when many keys hash to the same bin,
+    /// for example under adversarial input.
+    pub fn new_with_hasher_and_bin_lock(size: usize, hasher: S, threshold: usize) -> Self
+    {
+        Self {
+            table: Keep::new(Table::new_with_bin_lock(size, threshold)),
+            hasher: Keep::new_immutable(hasher).read(),
+            small: None,
+        }
+    }
+
+    /// Returns the number of bins of the map.
+    pub fn capacity(&self) -> usize
+    {
+        self.table.read().capacity()
+    }
+
+    /// Doubles the number of bins of the map.
+    ///
+    /// The bigger table is swapped in right away and the entries are migrated to it bin by bin.
+    /// Reads are never blocked and keep finding all present entries, by falling back to the old table
+    /// for bins which were not migrated yet. Writes to such bins wait until they were migrated.
+    /// Does nothing if the map is already being resized, or if the map is small,
+    /// since small maps are promoted on their own.
+    pub fn resize(&self)
+    {
+        self.resize_with(&());
+    }
+
+    /// Like `resize`, but reports each step of the resize to `observer`.
+    #[cfg(feature = "test-hooks")]
+    pub fn resize_observed(&self, observer: &impl ResizeObserver)
+    {
+        self.resize_with(observer);
+    }
+
+    fn resize_with(&self, observer: &impl ResizeObserver)
+    {
+        let table = self.table.read();
+
+        if table.is_small() || !table.begin_resize()
+        {
+            return;
+        }
+
+        let bigger = Table::new_migrating(&table);
+
+        // Fails if the table was replaced concurrently, in which case there is nothing left to resize.
+        self.migrate(table, bigger, observer);
+    }
+
+    /// Swaps in `new_table`, which takes over the entries of `table`, and migrates all bins to it.
+    ///
+    /// Returns `false` if `table` was replaced concurrently, in which case nothing is migrated.
+    fn migrate(
+        &self,
+        table: Guard<Table<Key, Val>>,
+        new_table: Table<Key, Val>,
+        observer: &impl ResizeObserver,
+    ) -> bool
+    {
+        let Ok(new_table) = self.table.exchange_fetch(&table, new_table)
+        else
+        {
+            return false;
+        };
+
+        let resizer = Resizer::new(Resizer::<Key, Val>::DEFAULT_STRIDE, table, new_table);
+        resizer.resize(observer);
+        resizer.finalize(observer);
+        true
+    }
+
     /// Tries to remove an entry from the map.
     pub fn remove(&self, key: &Key) -> Option<Keep<Val>>
     {
-        self.table.read().remove(key, self.hash(key))
+        let (table, _writer) = self.writable_table();
+        table.remove(key, self.hash(key))
+    }
+
+    /// Removes an entry from the map and returns its value.
+    ///
+    /// The value is moved out if nothing else refers to it anymore, otherwise it is cloned.
+    pub fn remove_value(&self, key: &Key) -> Option<Val>
+    where
+        Val: Clone,
+    {
+        match self.remove(key)?.try_unwrap()
+        {
+            Ok(val) => Some(val),
+            Err(keep) => Some((*keep.read()).clone()),
+        }
     }
 
     /// Inserts a new key-value pair into the map or updates an existing one...
@@ -44,7 +175,152 @@ where
     {
         let hash = self.hash(&key);
         let entry_node = EntryNode::new(key, val, hash);
-        self.table.read().insert(entry_node).0
+
+        let (table, writer) = self.writable_table();
+        let (old, resize) = table.insert(entry_node);
+
+        drop(writer);
+        self.promote_if_full(&table);
+
+        if resize
+        {
+            self.resize();
+        }
+
+        old
+    }
+
+    /// Returns the value associated with `key`, inserting the result of `f` if there is none.
+    ///
+    /// If `f` fails, nothing is inserted and the error is returned. If another thread inserts
+    /// `key` while `f` is running, the value of `f` is discarded and the present value is returned.
+    pub fn get_or_try_insert_with<E>(
+        &self,
+        key: Key,
+        f: impl FnOnce() -> Result<Val, E>,
+    ) -> Result<Guard<Val>, E>
+    {
+        if let Some(present) = self.get(&key)
+        {
+            return Ok(present);
+        }
+
+        let hash = self.hash(&key);
+        let entry_node = EntryNode::new(key, f()?, hash);
+        let inserted = entry_node.value().read();
+
+        let (table, writer) = self.writable_table();
+        let (present, resize) = table.try_insert(entry_node);
+
+        drop(writer);
+        self.promote_if_full(&table);
+
+        if resize
+        {
+            self.resize();
+        }
+
+        Ok(present.unwrap_or(inserted))
+    }
+
+    /// Atomically replaces all entries of the map with `new_entries`.
+    ///
+    /// The new entries are collected into a fresh table which is then swapped in,
+    /// so readers either observe all of the old or all of the new entries.
+    /// Guards to old values stay valid, but entries inserted or removed concurrently may be lost.
+    /// Waits for a running resize, rehash or promotion to finish.
+    pub fn replace_all(&self, new_entries: impl IntoIterator<Item = (Key, Val)>)
+    {
+        let new_entries: Vec<_> = new_entries
+            .into_iter()
+            .map(|(key, val)| {
+                let hash = self.hash(&key);
+                EntryNode::new(key, val, hash)
+            })
+            .collect();
+
+        loop
+        {
+            let old_table = self.table.read();
+
+            // A table still being migrated into would lose the entries migrated after the swap.
+            if !self.claim_swap(&old_table)
+            {
+                crate::yield_now();
+                continue;
+            }
+
+            let table = old_table.new_like(Table::<Key, Val>::size_for(
+                new_entries.len(),
+                old_table.size(),
+            ));
+
+            for entry_node in &new_entries
+            {
+                table.insert(entry_node.clone_striped());
+            }
+
+            if self.table.exchange(&old_table, table).is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Claims swapping out `table`, which keeps it from being resized, rehashed or promoted meanwhile.
+    ///
+    /// Returns `false` if another thread already claimed it, or if it is still being migrated into.
+    fn claim_swap(&self, table: &Table<Key, Val>) -> bool
+    {
+        match &self.small
+        {
+            Some(small) if table.is_small() => small.begin_promotion(),
+            _ => table.begin_resize(),
+        }
+    }
+
+    /// Spreads the entries over the bins anew, by mixing a fresh random seed into the bin index of their hashes.
+    ///
+    /// The hashes themselves are not recomputed, the map keeps its hasher. This mitigates hash flooding
+    /// with keys whose hashes only share the bits selecting their bin, like the keys of a map using
+    /// `IdentityState`. Keys whose full hashes collide still share a bin afterwards, defending against
+    /// those takes a new map with a different hasher. Waits for a running resize to finish.
+    ///
+    /// The entries are migrated bin by bin like during `resize`, so no concurrent write is lost.
+    /// Iterating the map waits until the rehash finished, while iterators started before it keep yielding
+    /// the entries of migrated bins as they were at the time of their migration.
+    ///
+    /// Only available with `std`, which provides the random seed.
+    #[cfg(feature = "std")]
+    pub fn rehash(&self)
+    {
+        loop
+        {
+            let table = self.table.read();
+
+            // A small table has a single bin, there is nothing to spread.
+            if table.is_small()
+            {
+                return;
+            }
+
+            // Claiming the resize keeps the table from being resized while it is copied.
+            if !table.begin_resize()
+            {
+                crate::yield_now();
+                continue;
+            }
+
+            // Zero leaves hashes as they are, so the seed always has its lowest bit set.
+            let seed = RandomState::new().hash_one(()) | 1;
+
+            let rehashed = Table::new_rehashing(&table, seed);
+
+            if self.migrate(table, rehashed, &())
+            {
+                return;
+            }
+        }
     }
 
     /// Tries to get a value associated with `key`. Returns `None` if no such value exists.
@@ -53,6 +329,277 @@ where
         self.table.read().get(key, self.hash(key))
     }
 
+    /// Replaces the value associated with `key` by `f` applied to it. Returns `false` if no such value exists.
+    ///
+    /// The new value is stored unconditionally, so a write to the same value racing `f` is overwritten.
+    pub fn modify(&self, key: &Key, f: impl FnOnce(&Val) -> Val) -> bool
+    {
+        self.table.read().modify(key, self.hash(key), f)
+    }
+
+    /// Removes all entries from the map, yielding each removed key and value.
+    ///
+    /// Entries inserted while draining may or may not be yielded.
+    pub fn drain(&self) -> impl Iterator<Item = (Guard<Key>, Keep<Val>)> + '_
+    {
+        self.iter().filter_map(|(key, _)| {
+            let val = self.remove(&key)?;
+            Some((key, val))
+        })
+    }
+
+    /// Consumes the map and returns an iterator over its owned values.
+    ///
+    /// Values that are still referenced elsewhere (by guards, `Keep`s or other clones of the map)
+    /// cannot be moved out and are skipped.
+    pub fn into_values(self) -> impl Iterator<Item = Val>
+    {
+        // Collect the keys first, so no guards to the values are held while unwrapping them.
+        let keys: Vec<_> = self.iter().map(|(key, _)| key).collect();
+
+        keys.into_iter()
+            .filter_map(move |key| self.remove(&key)?.try_unwrap().ok())
+    }
+
+    /// Returns an iterator over all key-value pairs in the map.
+    pub fn iter(&self) -> TablePairIter<Key, Val>
+    {
+        TablePairIter {
+            table: self.table.read(),
+            index: 0,
+            bin_buffer: vec![],
+        }
+    }
+
+    /// Calls `f` with every key-value pair in the map, without pinning the table.
+    ///
+    /// Unlike `iter`, which holds on to the table it started with until it is dropped,
+    /// this reads the current table anew for every bin, so the tables replaced by a concurrent resize
+    /// can be reclaimed while scanning. The consistency is weaker in turn: entries present for the whole
+    /// scan are visited at least once, but entries moved by a concurrent resize or rehash might be
+    /// visited more than once, and entries inserted or removed while scanning may or may not be visited.
+    pub fn scan(&self, mut f: impl FnMut(&Key, &Val))
+    {
+        let mut index = 0;
+        let mut layout = None;
+
+        loop
+        {
+            let table = self.table.read();
+
+            if let Some((capacity, seed)) = layout
+            {
+                index = table.scan_index(index, capacity, seed);
+            }
+
+            let Some(pairs) = table.buffered_pairs_at(index)
+            else
+            {
+                return;
+            };
+
+            layout = Some((table.capacity(), table.seed()));
+            drop(table);
+
+            for (key, val) in pairs
+            {
+                f(&key, &val);
+            }
+
+            index += 1;
+        }
+    }
+
+    /// Calls `f` with every key-value pair in the map, like iterating with `iter` does.
+    ///
+    /// `iter` buffers guards to all entries of a bin before yielding them, while this only holds a guard
+    /// to the value `f` is called with and drops it before moving on, which makes it the cheaper way
+    /// to scan a large map. The consistency is the same as for `iter`.
+    pub fn for_each(&self, mut f: impl FnMut(&Key, &Val))
+    {
+        let table = self.table.read();
+        let mut index = 0;
+
+        while table.for_each_at(index, &mut f)
+        {
+            index += 1;
+        }
+    }
+
+    /// Returns an iterator over handles to the values in the map.
+    ///
+    /// Unlike the guards yielded by `iter`, the handles can write new values, which are observed
+    /// by all readers of the map. A handle stays detached from the map once its entry is removed
+    /// or its value is replaced by `insert`.
+    pub fn iter_keeps(&self) -> impl Iterator<Item = Keep<Val>> + '_
+    {
+        self.iter().map(|(_, val)| val.into_keep())
+    }
+
+    /// Returns a snapshot of all key-value pairs in the map, sorted by key.
+    ///
+    /// This collects the pairs yielded by `iter` and sorts them, so it runs in O(n log n)
+    /// and entries inserted or removed while collecting may or may not be part of the snapshot.
+    pub fn iter_sorted(&self) -> Vec<(Guard<Key>, Guard<Val>)>
+    where
+        Key: Ord,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+        pairs
+    }
+
+    /// Returns a snapshot of all keys in the map.
+    ///
+    /// Like `iter_sorted`, entries inserted or removed while collecting may or may not be part of it.
+    pub fn keys_vec(&self) -> Vec<Guard<Key>>
+    {
+        let mut keys = Vec::with_capacity(self.entry_count());
+        keys.extend(self.iter().map(|(key, _)| key));
+        keys
+    }
+
+    /// Returns a snapshot of all values in the map, see `keys_vec`.
+    pub fn values_vec(&self) -> Vec<Guard<Val>>
+    {
+        let mut values = Vec::with_capacity(self.entry_count());
+        values.extend(self.iter().map(|(_, val)| val));
+        values
+    }
+
+    /// Returns clones of all keys in the map, see `keys_vec`.
+    ///
+    /// Each guard is dropped right after its key was cloned, so the snapshot doesn't keep removed entries alive.
+    pub fn keys_cloned(&self) -> Vec<Key>
+    where
+        Key: Clone,
+    {
+        let mut keys = Vec::with_capacity(self.entry_count());
+        keys.extend(self.iter().map(|(key, _)| (*key).clone()));
+        keys
+    }
+
+    /// Returns clones of all values in the map, see `keys_cloned`.
+    pub fn values_cloned(&self) -> Vec<Val>
+    where
+        Val: Clone,
+    {
+        let mut values = Vec::with_capacity(self.entry_count());
+        values.extend(self.into_iter().map(|val| (*val).clone()));
+        values
+    }
+
+    /// Walks every bin and reports how the entries are distributed over them.
+    ///
+    /// A `longest_chain` far above the average number of entries per bin points to a hasher
+    /// clustering the keys. Like `count_exact`, this runs in O(n).
+    pub fn bin_stats(&self) -> BinStats
+    {
+        let table = self.table.read();
+        let mut stats = BinStats::default();
+        let mut index = 0;
+
+        while let Some(pairs) = table.buffered_pairs_at(index)
+        {
+            if pairs.is_empty()
+            {
+                stats.empty_bins += 1;
+            }
+            else
+            {
+                stats.occupied_bins += 1;
+            }
+
+            stats.longest_chain = stats.longest_chain.max(pairs.len());
+            index += 1;
+        }
+
+        stats
+    }
+
+    /// Counts the live entries by walking every bin, independent of the entry counter.
+    ///
+    /// This runs in O(n), it is meant for validating the counter in tests.
+    /// Entries inserted or removed while counting may or may not be counted.
+    pub fn count_exact(&self) -> usize
+    {
+        self.iter().count()
+    }
+
+    /// Returns the number of entries in the map.
+    #[inline]
+    pub(crate) fn entry_count(&self) -> usize
+    {
+        self.table.read().entry_count()
+    }
+
+    /// Returns the table currently backing the map.
+    #[inline]
+    pub(crate) fn table(&self) -> &Keep<Table<Key, Val>>
+    {
+        &self.table
+    }
+
+    /// Returns the index of the bin `key` is stored in.
+    #[inline]
+    pub(crate) fn bin_of(&self, key: &Key) -> usize
+    {
+        self.table.read().index_of(self.hash(key))
+    }
+
+    /// Returns the table writes should go to.
+    ///
+    /// The small table of a map created by `new_small` is not promoted while the returned writer is alive.
+    fn writable_table(&self) -> (Guard<Table<Key, Val>>, Option<SmallWriter<'_>>)
+    {
+        let Some(small) = &self.small
+        else
+        {
+            return (self.table.read(), None);
+        };
+
+        loop
+        {
+            let table = self.table.read();
+
+            if !table.is_small()
+            {
+                return (table, None);
+            }
+
+            if let Some(writer) = small.begin_write()
+            {
+                return (table, Some(writer));
+            }
+
+            // The small table is being promoted, wait for the hashed table.
+            crate::yield_now();
+        }
+    }
+
+    /// Promotes `table` to a hashed table, if it is small and holds more entries than allowed.
+    fn promote_if_full(&self, table: &Table<Key, Val>)
+    {
+        let Some(small) = &self.small
+        else
+        {
+            return;
+        };
+
+        if !table.is_small() || table.entry_count() <= small.limit() || !small.begin_promotion()
+        {
+            return;
+        }
+
+        let hashed = table.new_like(Table::<Key, Val>::size_for(
+            table.entry_count(),
+            Self::DEFAULT_SIZE,
+        ));
+
+        table.copy_into(0..table.capacity(), &hashed);
+        self.table.write(hashed);
+    }
+
     #[inline]
     fn hash(&self, val: impl Hash) -> u64
     {
@@ -61,6 +608,20 @@ where
 }
 
 
+impl<Key, S> PlugMap<Key, Vec<u8>, S>
+where
+    Key: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Tries to get the byte blob associated with `key` as a slice.
+    pub fn get_slice(&self, key: &Key) -> Option<GuardedSlice>
+    {
+        self.get(key).map(GuardedSlice)
+    }
+}
+
+
+#[cfg(feature = "std")]
 impl<Key, Val> PlugMap<Key, Val, RandomState>
 where
     Key: Hash + Eq,
@@ -69,10 +630,88 @@ where
     {
         Self::new_with_hasher(Self::DEFAULT_SIZE, RandomState::new())
     }
+
+    /// Creates a new PlugMap for a handful of entries, which keeps them in a single linearly scanned bin.
+    ///
+    /// This only shrinks the bin array to a single bin, the entries are still allocated one by one
+    /// like in a hashed table rather than stored inline in a single allocation.
+    /// Once the map holds more than `SMALL_LIMIT` entries, it is promoted to a regular hashed table.
+    /// Writes wait while the promotion is in progress, reads are never blocked.
+    pub fn new_small() -> Self
+    {
+        Self {
+            table: Keep::new(Table::new_small()),
+            hasher: Keep::new_immutable(RandomState::new()).read(),
+            small: Some(Keep::new_immutable(Small::new(Self::SMALL_LIMIT)).read()),
+        }
+    }
+
+    /// Creates a new PlugMap which can hold `capacity` entries without exceeding its load factor.
+    pub fn with_capacity(capacity: usize) -> Self
+    {
+        Self::new_with_hasher(
+            Table::<Key, Val>::size_for(capacity, Self::DEFAULT_SIZE),
+            RandomState::new(),
+        )
+    }
+
+    /// Creates a new PlugMap with a capacity of `2^size`, which resizes once
+    /// a collision chain grows longer than `max_chain_len`, regardless of its load.
+    pub fn new_with_max_chain(size: usize, max_chain_len: usize) -> Self
+    {
+        Self {
+            table: Keep::new(Table::new_with_max_chain(size, max_chain_len)),
+            hasher: Keep::new_immutable(RandomState::new()).read(),
+            small: None,
+        }
+    }
+
+    /// Creates a new PlugMap which locks bins whose collision chain is longer than `threshold`.
+    ///
+    /// See `PlugMap::new_with_hasher_and_bin_lock`.
+    pub fn new_with_bin_lock(threshold: usize) -> Self
+    {
+        Self::new_with_hasher_and_bin_lock(Self::DEFAULT_SIZE, RandomState::new(), threshold)
+    }
+}
+
+
+#[cfg(feature = "std")]
+impl<Key, Val> PlugMap<Key, Val, SeededState>
+where
+    Key: Hash + Eq,
+{
+    /// Creates a new PlugMap with a capacity of `2^size` which hashes deterministically using `seed`.
+    pub fn with_seed(size: usize, seed: u64) -> Self
+    {
+        Self::new_with_hasher(size, SeededState::new(seed))
+    }
+}
+
+
+impl<Key, Val> PlugMap<Key, Val, IdentityState>
+where
+    Key: Hash + Eq,
+{
+    /// Creates a new PlugMap which uses the values of its keys as their hashes, see `IdentityState`.
+    pub fn new_with_identity_hasher() -> Self
+    {
+        Self::new_with_hasher(Self::DEFAULT_SIZE, IdentityState)
+    }
+
+    /// Like `new_with_identity_hasher`, but can hold `capacity` entries without exceeding its load factor.
+    pub fn with_capacity_and_identity_hasher(capacity: usize) -> Self
+    {
+        Self::new_with_hasher(
+            Table::<Key, Val>::size_for(capacity, Self::DEFAULT_SIZE),
+            IdentityState,
+        )
+    }
 }
 
 
-impl<Key, Val> Clone for PlugMap<Key, Val, RandomState>
+// Clones share the hasher through its guard, so it does not have to be `Clone` itself.
+impl<Key, Val, S> Clone for PlugMap<Key, Val, S>
 where
     Key: Hash + Eq,
 {
@@ -81,11 +720,13 @@ where
         Self {
             table: self.table.clone(),
             hasher: self.hasher.clone(),
+            small: self.small.clone(),
         }
     }
 }
 
 
+#[cfg(feature = "std")]
 impl<Key, Val> Default for PlugMap<Key, Val, RandomState>
 where
     Key: Hash + Eq,
@@ -1,15 +1,36 @@
-use crate::{entry::Entry, table::Table};
-use keep::*;
-use std::{
+use crate::{entry::Forward, table::Table};
+use core::{
     hash::Hash,
     sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
+use keep::*;
+
+
+/// Callbacks fired at the steps of a resize, which lets tests pause a resize at a
+/// specific step and drive other threads into a certain interleaving.
+///
+/// Every callback runs on the thread performing the step, so blocking in it blocks the resize.
+pub trait ResizeObserver: Sync
+{
+    /// A worker joined the resize, the bigger table is already in place.
+    fn worker_entered(&self) {}
+
+    /// A worker claimed the bins `start..end` of the old table and is about to migrate them.
+    fn stride_claimed(&self, _start: usize, _end: usize) {}
+
+    /// All bins were migrated and the bigger table stopped falling back to the old one.
+    fn swap_performed(&self) {}
+}
+
+
+/// Observes nothing, used by resizes outside of tests.
+impl ResizeObserver for () {}
 
 
 pub struct Resizer<Key, Val>
 {
     old_table: Guard<Table<Key, Val>>,
-    new_table: Keep<Table<Key, Val>>,
+    new_table: Forward<Key, Val>,
     stride: usize,
     old_capacity: usize,
     index: AtomicUsize,
@@ -22,10 +43,18 @@ impl<Key, Val> Resizer<Key, Val>
 where
     Key: Eq,
 {
-    pub fn new(stride: usize, old_table: Guard<Table<Key, Val>>) -> Self
+    /// The number of bins a worker migrates at once.
+    pub const DEFAULT_STRIDE: usize = 8;
+
+    /// Creates a resizer migrating the bins of `old_table` to `new_table`, see `Table::new_migrating`.
+    pub fn new(
+        stride: usize,
+        old_table: Guard<Table<Key, Val>>,
+        new_table: Guard<Table<Key, Val>>,
+    ) -> Self
     {
         Self {
-            new_table: Keep::new(old_table.new_bigger()),
+            new_table: Keep::new_immutable(new_table).read(),
             stride,
             old_capacity: old_table.capacity(),
             index: AtomicUsize::new(0),
@@ -38,12 +67,13 @@ where
     /// Helps with the resize
     ///
     /// will block until the resize is complete.
-    pub fn resize(&self)
+    pub fn resize(&self, observer: &impl ResizeObserver)
     {
         // increase worker count by one, since this thread is about to work on the resize
         self.workers.fetch_add(1, Ordering::SeqCst);
+        observer.worker_entered();
 
-        self.do_resize();
+        self.do_resize(observer);
 
         // decrease worker count by one because this thread is done helping.
         let mut workers = self.workers.fetch_sub(1, Ordering::SeqCst) - 1;
@@ -55,43 +85,33 @@ where
         }
     }
 
-    pub fn finalize(&self, old_table: &Keep<Table<Key, Val>>)
+    /// Lets the new table stop falling back to the old one, once all bins were migrated.
+    pub fn finalize(&self, observer: &impl ResizeObserver)
     {
         if !self.finished.swap(true, Ordering::SeqCst)
         {
-            old_table.swap_with(&self.new_table);
+            self.new_table.finish_migration();
+            observer.swap_performed();
         }
     }
 
-    fn do_resize(&self)
+    fn do_resize(&self, observer: &impl ResizeObserver)
     {
         loop
         {
             let start_index = self.index.fetch_add(self.stride, Ordering::SeqCst);
             let end_index = (start_index + self.stride).min(self.old_capacity);
 
-            if start_index > end_index
+            if start_index >= end_index
             {
                 break;
             }
 
-            let new_table = self.new_table.read();
+            observer.stride_claimed(start_index, end_index);
 
-            for entry in &self.old_table.entries()[start_index..end_index]
+            for index in start_index..end_index
             {
-                if let Entry::Head(head) = &*entry.read()
-                {
-                    new_table.insert(head.read().clone_striped());
-
-                    let mut current = head.read().next().read();
-
-                    while let Some(next) = &*current
-                    {
-                        let next = next.read();
-                        new_table.insert(next.clone_striped());
-                        current = next.next().read();
-                    }
-                }
+                self.old_table.migrate_bin(index, &self.new_table);
             }
         }
     }
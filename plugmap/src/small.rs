@@ -0,0 +1,81 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+
+/// Coordinates the writers of a small map with its promotion to a hashed table.
+///
+/// A small map keeps all entries in a single linearly scanned bin until it holds more than `limit` entries.
+/// Writers register themselves before writing to the small table, and the promotion waits for all of them,
+/// so no write to the small table can be lost while its entries are copied over.
+pub struct Small
+{
+    limit: usize,
+    writers: AtomicUsize,
+    promoted: AtomicBool,
+}
+
+
+impl Small
+{
+    pub fn new(limit: usize) -> Self
+    {
+        Self {
+            limit,
+            writers: AtomicUsize::new(0),
+            promoted: AtomicBool::new(false),
+        }
+    }
+
+    #[inline]
+    pub fn limit(&self) -> usize
+    {
+        self.limit
+    }
+
+    /// Registers a writer of the small table.
+    ///
+    /// Returns `None` if the table is being or was promoted, in which case the caller has to
+    /// write to the hashed table instead.
+    pub fn begin_write(&self) -> Option<SmallWriter<'_>>
+    {
+        self.writers.fetch_add(1, Ordering::SeqCst);
+
+        if self.promoted.load(Ordering::SeqCst)
+        {
+            self.writers.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+
+        Some(SmallWriter(&self.writers))
+    }
+
+    /// Claims the promotion and waits until all registered writers are done.
+    ///
+    /// Returns `false` if another thread already claimed the promotion.
+    pub fn begin_promotion(&self) -> bool
+    {
+        if self.promoted.swap(true, Ordering::SeqCst)
+        {
+            return false;
+        }
+
+        while self.writers.load(Ordering::SeqCst) != 0
+        {
+            crate::yield_now();
+        }
+
+        true
+    }
+}
+
+
+/// Unregisters its writer when dropped.
+pub struct SmallWriter<'a>(&'a AtomicUsize);
+
+
+impl Drop for SmallWriter<'_>
+{
+    fn drop(&mut self)
+    {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
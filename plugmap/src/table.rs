@@ -1,9 +1,14 @@
 use crate::{
     PlugMap,
-    entry::{Entry, EntryNode},
+    bin_lock::{BinLockGuard, BinLocks},
+    entry::{Entry, EntryNode, Forward, Update},
+};
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{
+    ops::Range,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 use keep::*;
-use std::sync::atomic::{AtomicUsize, Ordering};
 
 
 pub struct Table<Key, Val>
@@ -12,6 +17,19 @@ pub struct Table<Key, Val>
     capacity: usize,
     entry_count: AtomicUsize,
     entries: Box<[Keep<Entry<Key, Val>>]>,
+    bin_locks: Option<BinLocks>,
+
+    // Set once a resize of this table was started, see `Table::begin_resize`.
+    resizing: AtomicBool,
+
+    // The table whose entries are migrated into this one, see `Table::new_migrating`.
+    migrating_from: Keep<Option<Guard<Table<Key, Val>>>>,
+
+    // Chains longer than this request a resize, regardless of the load factor.
+    max_chain_len: usize,
+
+    // Mixed into hashes before they are mapped to bins, see `Table::new_rehashing`.
+    seed: u64,
 }
 
 
@@ -22,8 +40,19 @@ where
     pub fn new(size: usize) -> Self
     {
         // assert that the table has at least 16 entries.
-        let size = size.max(PlugMap::<Key, Val>::DEFAULT_SIZE);
+        Self::with_size(size.max(PlugMap::<Key, Val>::DEFAULT_SIZE))
+    }
+
+    /// Creates a table with a single bin, whose chain is scanned linearly.
+    ///
+    /// Its entries are allocated node by node like in any other table, only the bin array shrinks.
+    pub fn new_small() -> Self
+    {
+        Self::with_size(0)
+    }
 
+    fn with_size(size: usize) -> Self
+    {
         let mut entries = Box::new_uninit_slice(1 << size);
 
         for entry in &mut entries
@@ -36,14 +65,98 @@ where
             capacity: 1 << size,
             entry_count: AtomicUsize::new(0),
             entries: unsafe { entries.assume_init() },
+            bin_locks: None,
+            resizing: AtomicBool::new(false),
+            migrating_from: Keep::new(None),
+            max_chain_len: usize::MAX,
+            seed: 0,
+        }
+    }
+
+    /// Returns the smallest size of at least `min_size` at which `len` entries stay below the load factor.
+    pub fn size_for(len: usize, min_size: usize) -> usize
+    {
+        let mut size = min_size.max(PlugMap::<Key, Val>::DEFAULT_SIZE);
+
+        while len > (1 << size) - (1 << (size - 2))
+        {
+            size += 1;
         }
+
+        size
+    }
+
+    /// Creates a table which locks bins whose chains are longer than `threshold`.
+    pub fn new_with_bin_lock(size: usize, threshold: usize) -> Self
+    {
+        let mut table = Self::new(size);
+        table.bin_locks = Some(BinLocks::new(table.capacity, threshold));
+        table
+    }
+
+    /// Creates a table which requests a resize once a chain grows longer than `max_chain_len`.
+    pub fn new_with_max_chain(size: usize, max_chain_len: usize) -> Self
+    {
+        let mut table = Self::new(size);
+        table.max_chain_len = max_chain_len;
+        table
     }
 
     /// Creates a table with double the capacity
     #[inline]
     pub fn new_bigger(&self) -> Self
     {
-        Self::new(self.size + 1)
+        self.new_like(self.size + 1)
+    }
+
+    /// Creates a table with double the capacity of `table`, which takes over its entries bin by bin.
+    ///
+    /// Until `finish_migration` is called, reads fall back to `table` for bins which were not migrated yet,
+    /// and writes wait until their bin was migrated.
+    pub fn new_migrating(table: &Guard<Table<Key, Val>>) -> Self
+    {
+        let bigger = table.new_bigger();
+        bigger.migrating_from.write(Some(table.clone()));
+        bigger
+    }
+
+    /// Stops falling back to the table this table is migrating from, all of its bins have to be migrated.
+    pub fn finish_migration(&self)
+    {
+        self.migrating_from.write(None);
+    }
+
+    /// Creates an empty table with a capacity of `2^size` and the same configuration as `self`.
+    pub fn new_like(&self, size: usize) -> Self
+    {
+        let mut table = Self::new(size);
+
+        table.bin_locks = self
+            .bin_locks
+            .as_ref()
+            .map(|locks| BinLocks::new(table.capacity, locks.threshold()));
+        table.max_chain_len = self.max_chain_len;
+        table.seed = self.seed;
+
+        table
+    }
+
+    /// Creates a table of the same size and configuration as `table`, which takes over its entries
+    /// bin by bin like one created by `new_migrating`, but maps hashes to bins differently, depending on `seed`.
+    ///
+    /// A seed of zero maps hashes to bins by their lowest bits, like a table created by `new`.
+    pub fn new_rehashing(table: &Guard<Table<Key, Val>>, seed: u64) -> Self
+    {
+        let mut rehashed = table.new_like(table.size);
+        rehashed.seed = seed;
+        rehashed.migrating_from.write(Some(table.clone()));
+        rehashed
+    }
+
+    #[inline]
+    pub fn size(&self) -> usize
+    {
+        self.size
     }
 
     #[inline]
@@ -52,78 +165,627 @@ where
         self.capacity
     }
 
+    /// Returns `true` if this table was created by `new_small`.
+    #[inline]
+    pub fn is_small(&self) -> bool
+    {
+        self.capacity == 1
+    }
+
+    /// Inserts copies of all live entries in the bins `bins` into `table`.
+    ///
+    /// The copies share their values with the original entries.
+    pub fn copy_into(&self, bins: Range<usize>, table: &Table<Key, Val>)
+    {
+        for entry in &self.entries[bins]
+        {
+            if let Entry::Head(head) = &*entry.read()
+            {
+                let copied = Self::copy_chain(&head.read(), table);
+                table.entry_count.fetch_add(copied, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Inserts copies of all live nodes of the chain starting at `head` into `table`,
+    /// unless their keys are already present, and returns the number of inserted copies.
+    ///
+    /// The copies are not counted by `table`, that is up to the caller.
+    fn copy_chain(head: &EntryNode<Key, Val>, table: &Table<Key, Val>) -> usize
+    {
+        let mut copied = 0;
+        let mut copy = |node: &EntryNode<Key, Val>| {
+            // Bypasses `await_migration`, since the bin is only migrated once its chain was copied.
+            if !node.is_removed()
+                && let (Update::Appended(_), _) =
+                    table.upsert_node(Keep::new(node.clone_striped()), false, false)
+            {
+                copied += 1;
+            }
+        };
+
+        copy(head);
+        let mut current = head.next().read();
+
+        while let Some(next) = &*current
+        {
+            let next = next.read();
+            copy(&next);
+            current = next.next().read();
+        }
+
+        copied
+    }
+
+    /// Claims the resize of this table, returns `false` if another thread already claimed it
+    /// or if this table is still migrating from another one.
+    pub fn begin_resize(&self) -> bool
+    {
+        self.migrating_from.read().is_none() && !self.resizing.swap(true, Ordering::SeqCst)
+    }
+
+    /// Moves the entries of the bin at `index` to `table` and forwards the bin to it.
+    ///
+    /// The chain of the bin is frozen first, so no write to it can get lost while it is copied.
+    /// Writers hitting the frozen chain retry until the bin is forwarded, readers are never blocked:
+    /// they find all entries in the old chain until the bin is forwarded, and in `table` after that.
+    pub fn migrate_bin(&self, index: usize, table: &Forward<Key, Val>)
+    {
+        let entry = self.entry_at(index);
+
+        loop
+        {
+            let entry_guard = entry.read();
+
+            let frozen = match &*entry_guard
+            {
+                Entry::Moved(..) => return,
+                Entry::Empty => None,
+
+                Entry::Head(keep) =>
+                {
+                    let head = keep.read();
+                    head.freeze();
+                    let copied = Self::copy_chain(&head, table);
+
+                    // The frozen chain can't change anymore, so its copies are handed over to `table`
+                    // right away. Counting them while copying would count them in both tables.
+                    self.decrement_entry_count(copied);
+                    table.entry_count.fetch_add(copied, Ordering::SeqCst);
+
+                    (self.seed != table.seed).then(|| keep.clone())
+                }
+            };
+
+            // Fails if the bin got a head or a purge replaced the head, copying again is harmless.
+            if entry
+                .exchange(&entry_guard, Entry::Moved(table.clone(), frozen))
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    /// Returns the values of the bin at `index`, following migrated bins.
+    pub fn buffered_at(&self, index: usize) -> Option<Vec<Guard<Val>>>
+    {
+        let pairs = self.buffered_pairs_at(index)?;
+        Some(pairs.into_iter().map(|(_, val)| val).collect())
+    }
+
+    /// Returns the key-value pairs of the bin at `index`, following migrated bins.
+    pub fn buffered_pairs_at(&self, index: usize) -> Option<Vec<(Guard<Key>, Guard<Val>)>>
+    {
+        if let Some(table) = &*self.await_rehash()
+            && index < self.capacity
+        {
+            let old_entry = table.entry_at(index & (table.capacity - 1)).read();
+
+            // The old chain also holds the entries of the other bin it is migrated to.
+            if !matches!(&*old_entry, Entry::Moved(..))
+            {
+                return Some(old_entry.buffered_pairs_where(|hash| self.index_of(hash) == index));
+            }
+        }
+
+        let entry = self.entries.get(index)?.read();
+
+        let Entry::Moved(table, frozen) = &*entry
+        else
+        {
+            return Some(entry.buffered_pairs());
+        };
+
+        // The entries were scattered over all bins of `table` by a rehash.
+        if let Some(frozen) = frozen
+        {
+            let mut pairs = vec![];
+            frozen.read().buffered_pairs(&|_| true, &mut pairs);
+            return Some(pairs);
+        }
+
+        Some(
+            self.migrated_bins(index, table)
+                .flat_map(|index| table.buffered_pairs_at(index).unwrap_or_default())
+                .collect(),
+        )
+    }
+
+    /// Calls `f` with the key-value pairs of the bin at `index`, following migrated bins like `buffered_pairs_at`.
+    ///
+    /// Returns `false` if there is no bin at `index`.
+    pub fn for_each_at(&self, index: usize, f: &mut impl FnMut(&Key, &Val)) -> bool
+    {
+        if let Some(table) = &*self.await_rehash()
+            && index < self.capacity
+        {
+            let old_entry = table.entry_at(index & (table.capacity - 1)).read();
+
+            // The old chain also holds the entries of the other bin it is migrated to.
+            if !matches!(&*old_entry, Entry::Moved(..))
+            {
+                old_entry.for_each_where(|hash| self.index_of(hash) == index, f);
+                return true;
+            }
+        }
+
+        let Some(entry) = self.entries.get(index)
+        else
+        {
+            return false;
+        };
+
+        let entry = entry.read();
+
+        match &*entry
+        {
+            Entry::Moved(_, Some(frozen)) => frozen.read().for_each(&|_| true, f),
+
+            Entry::Moved(table, None) =>
+            {
+                for index in self.migrated_bins(index, table)
+                {
+                    table.for_each_at(index, f);
+                }
+            }
+
+            entry => entry.for_each_where(|_| true, f),
+        }
+
+        true
+    }
+
+    /// Waits until a rehash migrating into this table finished, returns the table it is migrating from otherwise.
+    ///
+    /// Each bin of a rehashed table may hold entries of any bin of the old table,
+    /// so its bins can only be walked once all of them were migrated.
+    fn await_rehash(&self) -> Guard<Option<Guard<Table<Key, Val>>>>
+    {
+        loop
+        {
+            let migrating_from = self.migrating_from.read();
+
+            match &*migrating_from
+            {
+                Some(table) if table.seed != self.seed => crate::yield_now(),
+                _ => return migrating_from,
+            }
+        }
+    }
+
+    /// Maps the position `index` of a scan over a table with `capacity` bins and `seed` onto this table.
+    ///
+    /// The bins before the returned index only hold entries of bins before `index` in the scanned table,
+    /// so continuing there never skips an entry, but might visit some of them twice.
+    pub fn scan_index(&self, index: usize, capacity: usize, seed: u64) -> usize
+    {
+        if seed != self.seed
+        {
+            // The entries were scattered differently, so the scan starts over.
+            0
+        }
+        else if capacity <= self.capacity
+        {
+            // Each bin of a grown table holds entries of a single old bin, whose index is not greater.
+            index
+        }
+        else
+        {
+            // Each bin of a shrunk table holds entries of old bins up to `capacity - self.capacity` after it.
+            index.saturating_sub(capacity - self.capacity)
+        }
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64
+    {
+        self.seed
+    }
+
+    /// Returns the bins of `table` the entries of the bin at `index` were migrated to.
+    fn migrated_bins(&self, index: usize, table: &Table<Key, Val>) -> impl Iterator<Item = usize>
+    {
+        (index..table.capacity).step_by(self.capacity)
+    }
+
     pub fn remove(&self, key: &Key, hash: u64) -> Option<Keep<Val>>
     {
-        let entry = self.entry_of(hash);
+        let index = self.index_of(hash);
+        let entry = self.entry_at(index);
+        self.await_migration(hash);
 
         loop
         {
-            let (entry_guard, marker) = entry.read_marked();
+            let _lock = self.lock_bin(index);
 
-            match &*entry_guard
+            let head = match &*entry.read()
             {
                 Entry::Empty => return None,
+                Entry::Moved(table, _) => return table.remove(key, hash),
+                Entry::Head(head) => head.read(),
+            };
 
-                Entry::Head(keep) =>
+            let Some(val) = head.remove(key)
+            else
+            {
+                if !head.is_frozen()
                 {
-                    let (entry_node, node_marker) = keep.read_marked();
+                    return None;
+                }
 
-                    if entry_node.key() == key
+                // The bin is being migrated, wait until it is forwarded.
+                drop(_lock);
+                crate::yield_now();
+                continue;
+            };
+
+            self.decrement_entry_count(1);
+            if let Some(locks) = &self.bin_locks
+            {
+                locks.shrunk(index);
+            }
+            self.purge(entry);
+
+            return Some(val);
+        }
+    }
+
+    /// Returns the number of entries in this table, including the ones which were not migrated yet.
+    #[inline]
+    pub fn entry_count(&self) -> usize
+    {
+        // Loaded before the source table's count, `migrate_bin` decrements the source count before
+        // incrementing this one, so entries migrated in between are not counted twice.
+        let migrated = self.entry_count.load(Ordering::SeqCst);
+        let unmigrated = match &*self.migrating_from.read()
+        {
+            Some(table) => table.entry_count(),
+            None => 0,
+        };
+
+        migrated + unmigrated
+    }
+
+    /// Decrements the entry count by `count`.
+    ///
+    /// Writers count their node before linking it in, see `upsert_node`, so every node handed over by
+    /// `migrate_bin` or unlinked by `remove` is already counted and the count can't drop below zero.
+    fn decrement_entry_count(&self, count: usize)
+    {
+        let decremented =
+            self.entry_count
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |current| {
+                    current.checked_sub(count)
+                });
+
+        // Release builds leave the count as it is instead of wrapping around.
+        debug_assert!(decremented.is_ok(), "entry count underflow");
+    }
+
+    /// Unlinks all removed nodes from the chain of `entry`.
+    ///
+    /// A node that gets resurrected by racing unlinks stays marked as removed and is
+    /// cleaned up by the next purge of its bin.
+    fn purge(&self, entry: &Keep<Entry<Key, Val>>)
+    {
+        'restart: loop
+        {
+            let entry_guard = entry.read();
+
+            let Entry::Head(head) = &*entry_guard
+            else
+            {
+                return;
+            };
+
+            let head = head.read();
+
+            if head.is_removed()
+            {
+                let replacement = match head.seal()
+                {
+                    Some(next) => Entry::Head(next),
+                    None => Entry::Empty,
+                };
+
+                let _ = entry.exchange(&entry_guard, replacement);
+                continue;
+            }
+
+            let mut prev = head;
+
+            loop
+            {
+                let next_guard = prev.next().read();
+
+                let Some(next) = &*next_guard
+                else
+                {
+                    return;
+                };
+
+                let node = next.read();
+
+                if node.is_removed()
+                {
+                    if prev.next().exchange(&next_guard, node.seal()).is_err()
                     {
-                        todo!()
+                        continue 'restart;
                     }
+
+                    // prev got a new successor, which needs to be checked as well.
+                    continue;
                 }
+
+                prev = node;
             }
         }
     }
 
     pub fn get(&self, key: &Key, hash: u64) -> Option<Guard<Val>>
     {
-        self.entry_of(hash).read().search(key)
+        // Read before searching this table, otherwise the migration could finish in between.
+        let migrating_from = self.migrating_from.read();
+
+        self.search(key, hash).or_else(|| {
+            let table = (*migrating_from).as_ref()?;
+
+            match &*table.entry_of(hash).read()
+            {
+                // The bin was migrated after the first search, so the entry is in this table by now.
+                Entry::Moved(..) => self.search(key, hash),
+                entry => entry.search(key, hash),
+            }
+        })
+    }
+
+    fn search(&self, key: &Key, hash: u64) -> Option<Guard<Val>>
+    {
+        match &*self.entry_of(hash).read()
+        {
+            Entry::Moved(table, _) => table.get(key, hash),
+            entry => entry.search(key, hash),
+        }
+    }
+
+    /// Returns how many nodes precede the node of `key` in its bin's chain, or `None` if `key` is not present.
+    ///
+    /// Removed nodes which were not unlinked yet are counted as well, this is meant for debugging
+    /// collisions and resizes. Bins which were migrated are followed.
+    pub fn chain_len(&self, key: &Key, hash: u64) -> Option<usize>
+    {
+        match &*self.entry_of(hash).read()
+        {
+            Entry::Moved(table, _) => table.chain_len(key, hash),
+            entry => entry.position(key),
+        }
+    }
+
+    pub fn modify(&self, key: &Key, hash: u64, f: impl FnOnce(&Val) -> Val) -> bool
+    {
+        if let Some(table) = &*self.migrating_from.read()
+        {
+            let old_entry = table.entry_of(hash).read();
+
+            // Values are shared with their copies, so the new value is not lost by the migration.
+            if !matches!(&*old_entry, Entry::Moved(..))
+            {
+                return old_entry.modify(key, f);
+            }
+        }
+
+        match &*self.entry_of(hash).read()
+        {
+            Entry::Moved(table, _) => table.modify(key, hash, f),
+            entry => entry.modify(key, f),
+        }
     }
 
     pub fn insert(&self, entry_node: EntryNode<Key, Val>) -> (Option<Keep<Val>>, bool)
     {
-        let entry = self.entry_of(entry_node.hash());
-        let entry_node = Keep::new(entry_node);
+        match self.upsert(entry_node, true)
+        {
+            (Update::Replaced(old), resize) => (Some(old), resize),
+            (_, resize) => (None, resize),
+        }
+    }
+
+    /// Inserts `entry_node` unless its key is already present, in which case the present value is returned.
+    pub fn try_insert(&self, entry_node: EntryNode<Key, Val>) -> (Option<Guard<Val>>, bool)
+    {
+        match self.upsert(entry_node, false)
+        {
+            (Update::Present(present), resize) => (Some(present), resize),
+            (_, resize) => (None, resize),
+        }
+    }
+
+    /// Inserts `entry_node`, replacing the value of an existing node only if `replace` is `true`.
+    ///
+    /// Returns the outcome together with whether the table needs to be resized.
+    fn upsert(&self, entry_node: EntryNode<Key, Val>, replace: bool) -> (Update<Val>, bool)
+    {
+        self.await_migration(entry_node.hash());
+        self.upsert_node(Keep::new(entry_node), replace, true)
+    }
+
+    /// Waits until the bin of `hash` was migrated from the table this table is migrating from.
+    fn await_migration(&self, hash: u64)
+    {
+        while let Some(table) = &*self.migrating_from.read()
+        {
+            if let Entry::Moved(..) = &*table.entry_of(hash).read()
+            {
+                return;
+            }
+
+            crate::yield_now();
+        }
+    }
+
+    /// Links `entry_node` into its bin, see `upsert`.
+    ///
+    /// Appended nodes are only counted if `counted` is `true`, `migrate_bin` counts
+    /// its copies once the whole chain was copied.
+    ///
+    /// The node is counted before it is linked in and uncounted again if it isn't appended.
+    /// Counting it afterwards would let `migrate_bin` hand over a count the table doesn't hold yet.
+    fn upsert_node(
+        &self,
+        entry_node: Keep<EntryNode<Key, Val>>,
+        replace: bool,
+        counted: bool,
+    ) -> (Update<Val>, bool)
+    {
+        let index = self.index_of(entry_node.read().hash());
+        let entry = self.entry_at(index);
+        let _lock = self.lock_bin(index);
+        let entry_count = counted.then(|| self.entry_count.fetch_add(1, Ordering::SeqCst) + 1);
+        let uncount = || {
+            if counted
+            {
+                self.decrement_entry_count(1);
+            }
+        };
 
         loop
         {
-            let (entry_guard, marker) = entry.read_marked();
+            let entry_guard = entry.read();
 
             match &*entry_guard
             {
                 Entry::Empty =>
                 {
-                    if entry.swap_with_marked(marker, &Keep::new(Entry::Head(entry_node.clone())))
+                    // A failed attempt to link the node in might have left it with a successor.
+                    if entry_node.read().next().read().is_some()
                     {
-                        let entry_count = self.entry_count.fetch_add(1, Ordering::SeqCst) + 1;
-                        return (None, self.resize_needed_up(entry_count));
+                        entry_node.read().next().write(None);
                     }
+
+                    if entry
+                        .exchange(&entry_guard, Entry::Head(entry_node.clone()))
+                        .is_ok()
+                    {
+                        return (Update::Appended(1), self.appended(index, 1, entry_count));
+                    }
+                }
+
+                Entry::Moved(table, _) =>
+                {
+                    drop(_lock);
+                    uncount();
+                    return table.upsert_node(entry_node, replace, counted);
                 }
 
                 Entry::Head(keep) =>
                 {
-                    match keep.read().update(&entry_node)
+                    let head = keep.read();
+
+                    let update = if entry_node.read().hash() < head.hash()
+                    {
+                        // The node comes first in the chain ordered by hash, so it becomes the new head.
+                        entry_node.read().next().write(Some(keep.clone()));
+
+                        match entry.exchange(&entry_guard, Entry::Head(entry_node.clone()))
+                        {
+                            Ok(_) => Update::Appended(entry_node.read().len()),
+                            Err(_) => continue,
+                        }
+                    }
+                    else
                     {
-                        Some(old) => return (Some(old), false),
-                        None =>
+                        head.update(&entry_node, replace)
+                    };
+
+                    match update
+                    {
+                        Update::Replaced(old) =>
+                        {
+                            uncount();
+                            return (Update::Replaced(old), false);
+                        }
+
+                        Update::Present(present) =>
+                        {
+                            uncount();
+                            return (Update::Present(present), false);
+                        }
+
+                        Update::Appended(chain_len) =>
                         {
-                            let entry_count = self.entry_count.fetch_add(1, Ordering::SeqCst) + 1;
-                            return (None, self.resize_needed_up(entry_count));
+                            return (
+                                Update::Appended(chain_len),
+                                self.appended(index, chain_len, entry_count),
+                            );
                         }
+
+                        // Help unlinking the removed nodes before trying again.
+                        Update::Retry => self.purge(entry),
                     }
                 }
             }
         }
     }
 
+    /// Accounts for a node appended to the bin at `index`, returns whether the table needs to be resized.
+    ///
+    /// `entry_count` is the count `upsert_node` reached by counting the node, `None` if it wasn't counted.
+    fn appended(&self, index: usize, chain_len: usize, entry_count: Option<usize>) -> bool
+    {
+        if let Some(locks) = &self.bin_locks
+        {
+            locks.grown(index);
+        }
+
+        let Some(entry_count) = entry_count
+        else
+        {
+            return false;
+        };
+
+        self.resize_needed_up(entry_count, chain_len)
+    }
+
+    /// Locks the bin at `index` if bin locking is enabled and its chain is too long.
+    #[inline]
+    pub fn lock_bin(&self, index: usize) -> Option<BinLockGuard<'_>>
+    {
+        self.bin_locks.as_ref()?.lock_if_long(index)
+    }
+
     /// Checks if the map needs to be resized up.
     ///
     /// This function assumes a power of two capacity greater than 2^2.
     #[inline]
-    fn resize_needed_up(&self, entry_count: usize) -> bool
+    fn resize_needed_up(&self, entry_count: usize, chain_len: usize) -> bool
     {
+        // Long chains hurt lookups even if the table is mostly empty.
+        if chain_len > self.max_chain_len
+        {
+            return true;
+        }
+
         /*
         This checks if entry_count is at least 75% of self.capacity.
         the expression at the return position of this function can be read like this,
@@ -139,9 +801,22 @@ where
     }
 
     #[inline]
-    fn index_of(&self, hash: u64) -> usize
+    pub fn index_of(&self, hash: u64) -> usize
+    {
+        self.scatter(hash) as usize & ((1 << self.size) - 1)
+    }
+
+    /// Mixes the seed into `hash`, so hashes which only differ in their high bits end up in different bins.
+    #[inline]
+    fn scatter(&self, hash: u64) -> u64
     {
-        hash as usize & ((1 << self.size) - 1)
+        if self.seed == 0
+        {
+            return hash;
+        }
+
+        let hash = (hash ^ self.seed ^ (hash >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+        hash ^ (hash >> 33)
     }
 
     #[inline]
@@ -192,7 +867,37 @@ where
             // if no item is available in the current buffer:
             // load the next bin into the bin_buffer and advance the bin index,
             // return None if all bins have been visited.
-            self.bin_buffer = self.table.entries.get(self.index)?.read().buffered();
+            self.bin_buffer = self.table.buffered_at(self.index)?;
+            self.index += 1;
+        }
+    }
+}
+
+
+pub struct TablePairIter<Key, Val>
+{
+    pub(crate) table: Guard<Table<Key, Val>>,
+    pub(crate) index: usize,
+    pub(crate) bin_buffer: Vec<(Guard<Key>, Guard<Val>)>,
+}
+
+
+impl<Key, Val> Iterator for TablePairIter<Key, Val>
+where
+    Key: Eq,
+{
+    type Item = (Guard<Key>, Guard<Val>);
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        loop
+        {
+            if let Some(item) = self.bin_buffer.pop()
+            {
+                return Some(item);
+            }
+
+            self.bin_buffer = self.table.buffered_pairs_at(self.index)?;
             self.index += 1;
         }
     }